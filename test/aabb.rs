@@ -8,14 +8,19 @@ pub struct Aabb {
 #[rustfmt::skip]
 impl Aabb {
     pub fn new(min: Vec3, max: Vec3) -> Self {
-        Self {
-            min: min,
-            max: max
-        }
+        Self { min, max }
     }
     pub fn contains(&self,other: Aabb) -> bool{
         self.min.cmple(other.min).all() && self.max.cmpge(other.max).all()
     }
+    // Пересечение с касанием, как в DynamicBvh::query
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+    }
+    // Квадрат расстояния от точки до бокса, 0 — точка внутри
+    pub fn distance_sq(&self, p: Vec3) -> f32 {
+        (self.min - p).max(p - self.max).max(Vec3::ZERO).length_squared()
+    }
     pub fn merge(&mut self, other: &Aabb) {
         self.min = self.min.min(other.min);
         self.max = self.max.max(other.max);
@@ -28,7 +33,7 @@ impl Aabb {
     }
     pub fn area(a:&Aabb) -> f32{
         let d = a.max - a.min;
-        return 2.0 * (d.x * d.y + d.y*d.z + d.z*d.x);
+        2.0 * (d.x * d.y + d.y*d.z + d.z*d.x)
     }
     pub fn intersect_ray(&self, ray: &Ray) -> bool {
         let t1 = (self.min - ray.origin) * ray.inv_dir;
@@ -42,4 +47,14 @@ impl Aabb {
 
         t_exit >= t_enter && t_exit > 0.0
     }
+    // То же, что intersect_ray, но с расстоянием (в единицах direction) до входа; 0 — луч начинается внутри
+    pub fn ray_hit(&self, ray: &Ray) -> Option<f32> {
+        let t1 = (self.min - ray.origin) * ray.inv_dir;
+        let t2 = (self.max - ray.origin) * ray.inv_dir;
+
+        let t_enter = t1.min(t2).max_element();
+        let t_exit = t1.max(t2).min_element();
+
+        if t_exit >= t_enter && t_exit > 0.0 { Some(t_enter.max(0.0)) } else { None }
+    }
 }
@@ -0,0 +1,90 @@
+// Засыпание сущностей. Всё, что двигается (update_position, set_*, дети за родителем) или появляется,
+// попадает в World::recently_moved. На step сдвинутые будят спящих в пределах wake_margin от себя,
+// а бодрствующие без движения копят idle_steps и через sleep_after шагов засыпают.
+// Спящий триггер не проверяется и сохраняет прежние контакты; отключённые (Disabled) не участвуют
+// ни в триггерах, ни в зонах, ни в запросах по умолчанию — и сами не просыпаются.
+use crate::Aabb;
+use crate::Vec3;
+use crate::entity::Activity;
+use crate::filter::QueryFilter;
+use crate::world::World;
+
+#[rustfmt::skip]
+impl World {
+    // Отключённую сущность включает только явный set_activity
+    pub fn set_activity(&mut self, id: i32, activity: Activity) -> bool {
+        let Some(e) = self.registry.get_mut(id) else { return false };
+        e.activity = activity;
+        e.idle_steps = 0;
+        true
+    }
+    pub fn wake(&mut self, id: i32) -> bool {
+        let Some(e) = self.registry.get_mut(id) else { return false };
+        if e.activity != Activity::Sleeping { return false; }
+        e.activity = Activity::Awake;
+        e.idle_steps = 0;
+        true
+    }
+    // Сущность сдвинулась: сама просыпается, соседей разбудит ближайший step
+    pub(crate) fn touch(&mut self, id: i32) {
+        self.wake(id);
+        self.recently_moved.insert(id);
+    }
+    pub(crate) fn update_activity(&mut self) {
+        let moved = std::mem::take(&mut self.recently_moved);
+        let margin = Vec3::splat(self.wake_margin);
+        let mut near = Vec::new();
+        for &id in &moved {
+            let Some(e) = self.registry.get(id) else { continue };
+            if e.activity == Activity::Disabled { continue; }
+            let b = e.get_aabb();
+            self.query(&Aabb::new(b.min - margin, b.max + margin), &QueryFilter::any(), &mut near);
+        }
+        for id in near { self.wake(id); }
+
+        for e in self.registry.iter_mut() {
+            if e.activity != Activity::Awake { continue; }
+            if moved.contains(&e.id) {
+                e.idle_steps = 0;
+                continue;
+            }
+            e.idle_steps += 1;
+            if self.sleep_after > 0 && e.idle_steps >= self.sleep_after { e.activity = Activity::Sleeping; }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Aabb;
+    use crate::Vec3;
+    use crate::entity::Activity;
+    use crate::filter::QueryFilter;
+    use crate::world::World;
+
+    #[test]
+    fn moving_entity_wakes_sleeping_neighbours() {
+        let mut world = World::new();
+        world.sleep_after = 2;
+        let a = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 0);
+        let b = world.create_entity(Vec3::new(1.2, 0.0, 0.0), Vec3::ONE, 1, 0);
+        let far = world.create_entity(Vec3::new(10.0, 0.0, 0.0), Vec3::ONE, 1, 0);
+        for _ in 0..3 { world.step(0.1); }
+        assert!(world.registry.iter().all(|e| e.activity == Activity::Sleeping));
+
+        world.update_position(a, Vec3::new(0.1, 0.0, 0.0));
+        assert_eq!(world.registry[a].activity, Activity::Awake);
+        world.step(0.1);
+        assert_eq!(world.registry[b].activity, Activity::Awake, "neighbour within wake_margin");
+        assert_eq!(world.registry[far].activity, Activity::Sleeping);
+
+        // Отключённую не будят ни движение рядом, ни запросы по умолчанию её не видят
+        world.set_activity(b, Activity::Disabled);
+        world.update_position(a, Vec3::new(0.2, 0.0, 0.0));
+        world.step(0.1);
+        assert_eq!(world.registry[b].activity, Activity::Disabled);
+        let mut hits = Vec::new();
+        world.query(&Aabb::new(Vec3::splat(-5.0), Vec3::splat(5.0)), &QueryFilter::any(), &mut hits);
+        assert_eq!(hits, vec![a]);
+    }
+}
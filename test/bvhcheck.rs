@@ -0,0 +1,75 @@
+// Инварианты DynamicBvh сами по себе, без World: их проверяют и validate_world, и фаззер,
+// который подключает только модули дерева.
+use crate::DynamicBvh;
+
+// Проверка структурных инвариантов дерева. Возвращает число листьев.
+// Обход ограничен размером nodes, поэтому на испорченном дереве (циклы, дубли) не зависает.
+pub fn validate_bvh(bvh: &DynamicBvh) -> Result<usize, String> {
+    let n = bvh.nodes.len();
+    let in_range = |idx: i32| idx >= 0 && (idx as usize) < n;
+    let mut reachable = vec![false; n];
+    let mut leaves = 0;
+
+    if bvh.root != -1 {
+        if !in_range(bvh.root) {
+            return Err(format!("root {} out of range", bvh.root));
+        }
+        let root_parent = bvh.nodes[bvh.root as usize].parent_index;
+        if root_parent != -1 {
+            return Err(format!("root {} has parent {}", bvh.root, root_parent));
+        }
+        let mut stack = vec![bvh.root];
+        while let Some(idx) = stack.pop() {
+            if reachable[idx as usize] {
+                return Err(format!("node {} reachable twice", idx));
+            }
+            reachable[idx as usize] = true;
+            let node = &bvh.nodes[idx as usize];
+            if node.is_leaf {
+                if node.height != 0 {
+                    return Err(format!("leaf {} has height {}", idx, node.height));
+                }
+                leaves += 1;
+                continue;
+            }
+            for c in [node.child1, node.child2] {
+                if !in_range(c) {
+                    return Err(format!("node {} has child {} out of range", idx, c));
+                }
+                let child = &bvh.nodes[c as usize];
+                if child.parent_index != idx {
+                    return Err(format!("node {} has parent {}, expected {}", c, child.parent_index, idx));
+                }
+                if !node.bbox.contains(child.bbox) {
+                    return Err(format!("node {} bbox does not contain child {}", idx, c));
+                }
+                stack.push(c);
+            }
+            let c1 = &bvh.nodes[node.child1 as usize];
+            let c2 = &bvh.nodes[node.child2 as usize];
+            // Дети уже проверены на выход за границы выше, высоты читаем безопасно
+            let expected = 1 + i32::max(c1.height, c2.height);
+            if node.height != expected {
+                return Err(format!("node {} has height {}, expected {}", idx, node.height, expected));
+            }
+        }
+    }
+
+    // Свободный список не пересекается с деревом, и вместе они покрывают все узлы
+    let mut curr = bvh.free_list;
+    while curr != -1 {
+        if !in_range(curr) {
+            return Err(format!("free list entry {} out of range", curr));
+        }
+        if reachable[curr as usize] {
+            return Err(format!("node {} is both in the tree and in the free list", curr));
+        }
+        reachable[curr as usize] = true;
+        curr = bvh.nodes[curr as usize].next;
+    }
+    let used = reachable.iter().filter(|r| **r).count();
+    if used != n {
+        return Err(format!("{} of {} nodes are neither in the tree nor in the free list", n - used, n));
+    }
+    Ok(leaves)
+}
@@ -0,0 +1,236 @@
+// Отложенные структурные изменения мира. Пока идёт обход результатов запроса, обработчик события
+// или параллельная система, мир трогать нельзя — команды записываются в WorldCommands и применяются
+// потом, все сразу, через World::apply_commands.
+//
+// Порядок применения фиксирован и не зависит от того, кто что записал первым между буферами:
+//   1. spawn — в порядке записи (id выдаются в этом порядке);
+//   2. move / resize / rotate / set-layers — в порядке записи через World::set_*, для одной сущности побеждает
+//      последняя запись; дерево обновляется одним World::flush на все затронутые сущности;
+//   3. despawn — через mark_for_deletion и cleanup.
+use crate::Vec3;
+use crate::dynbvh::UpdateStrategy;
+use crate::world::World;
+use glam::Quat;
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Spawn { pos: Vec3, size: Vec3, category: i32, mask: i32 },
+    Despawn { target: Target },
+    Move { target: Target, pos: Vec3 },
+    Resize { target: Target, size: Vec3 },
+    SetLayers { target: Target, category: i32, mask: i32 },
+    Rotate { target: Target, rotation: Option<Quat> },
+}
+
+impl Command {
+    fn target_mut(&mut self) -> Option<&mut Target> {
+        match self {
+            Command::Spawn { .. } => None,
+            Command::Despawn { target } | Command::Move { target, .. } | Command::Resize { target, .. }
+            | Command::SetLayers { target, .. } | Command::Rotate { target, .. } => Some(target),
+        }
+    }
+}
+
+// Номер spawn в буфере; реальный id — spawned[handle.0] в результате apply_commands
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpawnHandle(pub usize);
+
+// Кому адресована команда: уже живой сущности или spawn из этого же буфера (id появится только при apply)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Id(i32),
+    Spawned(SpawnHandle),
+}
+
+impl From<i32> for Target {
+    fn from(id: i32) -> Self {
+        Target::Id(id)
+    }
+}
+
+impl From<SpawnHandle> for Target {
+    fn from(handle: SpawnHandle) -> Self {
+        Target::Spawned(handle)
+    }
+}
+
+impl Target {
+    // -1, если handle не из этого буфера
+    fn resolve(self, spawned: &[i32]) -> i32 {
+        match self {
+            Target::Id(id) => id,
+            Target::Spawned(h) => spawned.get(h.0).copied().unwrap_or(-1),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WorldCommands {
+    commands: Vec<Command>,
+    spawns: usize,
+}
+
+pub struct Applied {
+    pub spawned: Vec<i32>,
+    pub strategy: UpdateStrategy,
+}
+
+impl WorldCommands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+    pub fn spawn(&mut self, pos: Vec3, size: Vec3, category: i32, mask: i32) -> SpawnHandle {
+        self.commands.push(Command::Spawn { pos, size, category, mask });
+        self.spawns += 1;
+        SpawnHandle(self.spawns - 1)
+    }
+    pub fn spawns(&self) -> usize {
+        self.spawns
+    }
+    pub fn despawn(&mut self, target: impl Into<Target>) {
+        self.commands.push(Command::Despawn { target: target.into() });
+    }
+    pub fn move_to(&mut self, target: impl Into<Target>, pos: Vec3) {
+        self.commands.push(Command::Move { target: target.into(), pos });
+    }
+    pub fn resize(&mut self, target: impl Into<Target>, size: Vec3) {
+        self.commands.push(Command::Resize { target: target.into(), size });
+    }
+    pub fn set_layers(&mut self, target: impl Into<Target>, category: i32, mask: i32) {
+        self.commands.push(Command::SetLayers { target: target.into(), category, mask });
+    }
+    pub fn rotate(&mut self, target: impl Into<Target>, rotation: Option<Quat>) {
+        self.commands.push(Command::Rotate { target: target.into(), rotation });
+    }
+    // Дописывает чужой буфер (например, от другой системы) в конец этого. Spawn из other получают номера
+    // после своих: возвращается сдвиг, handle h из other здесь — SpawnHandle(h.0 + offset). Команды other,
+    // адресованные его spawn, перенумеровываются сразу.
+    pub fn append(&mut self, other: &mut WorldCommands) -> usize {
+        let offset = self.spawns;
+        for c in &mut other.commands {
+            if let Some(Target::Spawned(h)) = c.target_mut() { h.0 += offset; }
+        }
+        self.spawns += other.spawns;
+        other.spawns = 0;
+        self.commands.append(&mut other.commands);
+        offset
+    }
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+}
+
+impl World {
+    pub fn apply_commands(&mut self, mut buffer: WorldCommands) -> Applied {
+        let commands = std::mem::take(&mut buffer.commands);
+
+        let mut spawned = Vec::with_capacity(buffer.spawns);
+        for c in &commands {
+            if let Command::Spawn { pos, size, category, mask } = *c {
+                spawned.push(self.create_entity(pos, size, category, mask));
+            }
+        }
+
+        for c in &commands {
+            match *c {
+                Command::Move { target, pos } => {
+                    let id = target.resolve(&spawned);
+                    if let Some(size) = self.registry.get(id).map(|e| e.size) { self.set_transform(id, pos, size); }
+                }
+                Command::Resize { target, size } => { self.set_size(target.resolve(&spawned), size); }
+                Command::SetLayers { target, category, mask } => { self.set_layers(target.resolve(&spawned), category, mask); }
+                Command::Rotate { target, rotation } => { self.set_rotation(target.resolve(&spawned), rotation); }
+                Command::Spawn { .. } | Command::Despawn { .. } => {}
+            }
+        }
+        let strategy = self.flush();
+
+        for c in &commands {
+            if let Command::Despawn { target } = *c { self.mark_for_deletion(target.resolve(&spawned)); }
+        }
+        self.cleanup();
+        Applied { spawned, strategy }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpawnHandle, WorldCommands};
+    use crate::Vec3;
+    use crate::world::World;
+    use glam::Quat;
+
+    #[test]
+    fn last_write_per_entity_wins_across_buffers() {
+        let mut world = World::new();
+        let id = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1);
+        let (mut physics, mut script) = (WorldCommands::new(), WorldCommands::new());
+        physics.move_to(id, Vec3::X);
+        let rock = physics.spawn(Vec3::splat(5.0), Vec3::ONE, 1, 1);
+        script.move_to(id, Vec3::Y);
+        script.resize(id, Vec3::splat(2.0));
+        let spark = script.spawn(Vec3::splat(9.0), Vec3::ONE, 1, 1);
+        script.move_to(spark, Vec3::splat(7.0));
+        physics.move_to(id, Vec3::Z);
+        assert_eq!((rock, spark), (SpawnHandle(0), SpawnHandle(0)));
+
+        // Буфер, дописанный последним, пишет последним; его handle сдвигаются на число spawn первого
+        let offset = physics.append(&mut script);
+        assert!(script.is_empty() && physics.len() == 7 && physics.spawns() == 2);
+        let spark = SpawnHandle(spark.0 + offset);
+        let applied = world.apply_commands(physics);
+        assert_eq!((world.registry[id].pos, world.registry[id].size), (Vec3::Y, Vec3::splat(2.0)));
+        let at = |h: SpawnHandle| world.registry[applied.spawned[h.0]].pos;
+        assert_eq!((applied.spawned.len(), at(rock), at(spark)), (2, Vec3::splat(5.0), Vec3::splat(7.0)));
+    }
+
+    #[test]
+    fn spawned_entities_are_configured_in_the_same_buffer() {
+        let mut world = World::new();
+        let mut buffer = WorldCommands::new();
+        let kept = buffer.spawn(Vec3::ZERO, Vec3::ONE, 1, 1);
+        let doomed = buffer.spawn(Vec3::X * 4.0, Vec3::ONE, 1, 1);
+        buffer.move_to(kept, Vec3::Y);
+        buffer.resize(kept, Vec3::splat(3.0));
+        buffer.set_layers(kept, 2, 4);
+        buffer.rotate(kept, Some(Quat::from_rotation_y(0.5)));
+        buffer.despawn(doomed);
+        // Чужой handle ни во что не разрешается
+        buffer.move_to(SpawnHandle(9), Vec3::Z);
+        let applied = world.apply_commands(buffer);
+
+        let e = &world.registry[applied.spawned[kept.0]];
+        assert_eq!((e.pos, e.size, e.category, e.mask), (Vec3::Y, Vec3::splat(3.0), 2, 4));
+        assert!(e.rotation.is_some());
+        assert!(!world.registry.contains(applied.spawned[doomed.0]));
+        assert_eq!((world.registry.len(), world.bvh.proxy_count), (1, 1));
+    }
+
+    #[test]
+    fn one_flush_per_apply() {
+        let mut world = World::new();
+        let ids: Vec<i32> = (0..10).map(|i| world.create_entity(Vec3::new(i as f32 * 3.0, 0.0, 0.0), Vec3::ONE, 1, 1)).collect();
+        let doomed = ids[0];
+        let mut buffer = WorldCommands::new();
+        for &id in &ids {
+            buffer.move_to(id, world.registry[id].pos + Vec3::Y);
+            buffer.move_to(id, world.registry[id].pos + Vec3::Z);
+        }
+        buffer.despawn(doomed);
+        let moves = world.bvh.stats.moves;
+        world.apply_commands(buffer);
+
+        // Каждый лист обновлён один раз, хотя записей по две на сущность
+        assert_eq!(world.bvh.stats.moves - moves, ids.len() as u64);
+        assert!(world.dirty.is_empty() && !world.registry.contains(doomed));
+        assert_eq!(world.registry[ids[1]].pos, Vec3::new(3.0, 0.0, 1.0));
+        assert_eq!(world.bvh.proxy_count, 9);
+    }
+}
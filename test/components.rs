@@ -0,0 +1,271 @@
+// Произвольные типизированные компоненты сущностей: по одному sparse set на тип.
+// Значения лежат подряд (итерация по типу — проход по плотному массиву), sparse индексируется слотом id
+// из store.rs, а полный id рядом со значением отсекает устаревшие поколения.
+use crate::store::slot_of;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+pub struct ComponentStore<T> {
+    values: Vec<T>,
+    ids: Vec<i32>,    // ids[i] — владелец values[i]
+    sparse: Vec<i32>, // слот сущности -> индекс в values, -1 — нет компонента
+}
+
+#[rustfmt::skip]
+impl<T> ComponentStore<T> {
+    fn new() -> Self {
+        Self { values: Vec::new(), ids: Vec::new(), sparse: Vec::new() }
+    }
+    fn index(&self, id: i32) -> Option<usize> {
+        let i = *self.sparse.get(slot_of(id).max(0) as usize)?;
+        if i == -1 || self.ids[i as usize] != id { return None; }
+        Some(i as usize)
+    }
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    pub fn contains(&self, id: i32) -> bool {
+        self.index(id).is_some()
+    }
+    pub fn get(&self, id: i32) -> Option<&T> {
+        self.index(id).map(|i| &self.values[i])
+    }
+    pub fn get_mut(&mut self, id: i32) -> Option<&mut T> {
+        self.index(id).map(|i| &mut self.values[i])
+    }
+    // Возвращает прежнее значение, если компонент уже был
+    pub fn insert(&mut self, id: i32, value: T) -> Option<T> {
+        if let Some(i) = self.index(id) {
+            return Some(std::mem::replace(&mut self.values[i], value));
+        }
+        let slot = slot_of(id) as usize;
+        if self.sparse.len() <= slot { self.sparse.resize(slot + 1, -1); }
+        // В слоте мог остаться компонент прежнего поколения — он уже мёртв
+        if self.sparse[slot] != -1 { self.remove_at(self.sparse[slot] as usize); }
+        self.sparse[slot] = self.values.len() as i32;
+        self.values.push(value);
+        self.ids.push(id);
+        None
+    }
+    pub fn remove(&mut self, id: i32) -> Option<T> {
+        self.index(id).map(|i| self.remove_at(i))
+    }
+    fn remove_at(&mut self, i: usize) -> T {
+        self.sparse[slot_of(self.ids[i]) as usize] = -1;
+        let value = self.values.swap_remove(i);
+        self.ids.swap_remove(i);
+        if let Some(&moved) = self.ids.get(i) {
+            self.sparse[slot_of(moved) as usize] = i as i32;
+        }
+        value
+    }
+    pub fn ids(&self) -> &[i32] {
+        &self.ids
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &T)> {
+        self.ids.iter().copied().zip(self.values.iter())
+    }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (i32, &mut T)> {
+        self.ids.iter().copied().zip(self.values.iter_mut())
+    }
+}
+
+// Стирание типа, чтобы хранить сторы всех типов в одной карте и чистить их при удалении сущности
+trait AnyStore {
+    fn remove_entity(&mut self, id: i32);
+    fn len(&self) -> usize;
+    fn contains(&self, id: i32) -> bool;
+    fn owners(&self) -> &[i32];
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStore for ComponentStore<T> {
+    fn remove_entity(&mut self, id: i32) {
+        self.remove(id);
+    }
+    fn len(&self) -> usize {
+        ComponentStore::len(self)
+    }
+    fn contains(&self, id: i32) -> bool {
+        ComponentStore::contains(self, id)
+    }
+    fn owners(&self) -> &[i32] {
+        self.ids()
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Набор типов компонентов для выборки: (A,), (A, B), (A, B, C), (A, B, C, D)
+pub trait ComponentSet {
+    fn type_ids() -> Vec<TypeId>;
+}
+
+macro_rules! component_set {
+    ($($t:ident),+) => {
+        impl<$($t: 'static),+> ComponentSet for ($($t,)+) {
+            fn type_ids() -> Vec<TypeId> {
+                vec![$(TypeId::of::<$t>()),+]
+            }
+        }
+    };
+}
+component_set!(A);
+component_set!(A, B);
+component_set!(A, B, C);
+component_set!(A, B, C, D);
+
+pub struct Components {
+    stores: HashMap<TypeId, Box<dyn AnyStore>>,
+}
+
+#[rustfmt::skip]
+impl Components {
+    pub fn new() -> Self {
+        Self { stores: HashMap::new() }
+    }
+    pub fn store<T: 'static>(&self) -> Option<&ComponentStore<T>> {
+        self.stores.get(&TypeId::of::<T>()).and_then(|s| s.as_any().downcast_ref())
+    }
+    pub fn store_mut<T: 'static>(&mut self) -> &mut ComponentStore<T> {
+        self.stores.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(ComponentStore::<T>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+    pub fn insert<T: 'static>(&mut self, id: i32, value: T) -> Option<T> {
+        self.store_mut::<T>().insert(id, value)
+    }
+    pub fn remove<T: 'static>(&mut self, id: i32) -> Option<T> {
+        self.stores.get_mut(&TypeId::of::<T>())?.as_any_mut().downcast_mut::<ComponentStore<T>>()?.remove(id)
+    }
+    pub fn get<T: 'static>(&self, id: i32) -> Option<&T> {
+        self.store::<T>()?.get(id)
+    }
+    pub fn get_mut<T: 'static>(&mut self, id: i32) -> Option<&mut T> {
+        self.stores.get_mut(&TypeId::of::<T>())?.as_any_mut().downcast_mut::<ComponentStore<T>>()?.get_mut(id)
+    }
+    pub fn has<T: 'static>(&self, id: i32) -> bool {
+        self.store::<T>().is_some_and(|s| s.contains(id))
+    }
+    pub fn iter<T: 'static>(&self) -> impl Iterator<Item = (i32, &T)> {
+        self.store::<T>().into_iter().flat_map(|s| s.iter())
+    }
+    pub fn iter_mut<T: 'static>(&mut self) -> impl Iterator<Item = (i32, &mut T)> {
+        self.store_mut::<T>().iter_mut()
+    }
+    // Сущности, у которых есть все компоненты набора. Обходим самый маленький стор, остальные только проверяем.
+    pub fn ids_with<S: ComponentSet>(&self) -> Vec<i32> {
+        let mut stores = Vec::new();
+        for t in S::type_ids() {
+            match self.stores.get(&t) {
+                Some(s) => stores.push(s.as_ref()),
+                None => return Vec::new(),
+            }
+        }
+        stores.sort_by_key(|s| s.len());
+        let (first, rest) = stores.split_first().unwrap();
+        first.owners().iter().copied().filter(|&id| rest.iter().all(|s| s.contains(id))).collect()
+    }
+    // Владельцы компонентов всех типов (с повторами) — для проверки, что мёртвые сущности ничего не держат
+    pub fn owners(&self) -> impl Iterator<Item = i32> + '_ {
+        self.stores.values().flat_map(|s| s.owners().iter().copied())
+    }
+    // Вызывается миром при удалении сущности
+    pub fn remove_entity(&mut self, id: i32) {
+        for store in self.stores.values_mut() { store.remove_entity(id); }
+    }
+    pub fn clear(&mut self) {
+        self.stores.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ComponentStore, Components};
+    use crate::Vec3;
+    use crate::entity::Entity;
+    use crate::store::EntityStore;
+    use crate::world::World;
+
+    #[test]
+    fn store_insert_replace_and_remove() {
+        let mut store = ComponentStore::new();
+        assert!(store.is_empty());
+        assert_eq!(store.insert(1, "a"), None);
+        assert_eq!(store.insert(2, "b"), None);
+        assert_eq!(store.insert(3, "c"), None);
+        assert_eq!(store.insert(2, "B"), Some("b"));
+        assert_eq!(store.len(), 3);
+
+        // Последний переезжает на место удалённого — поиск по id не ломается
+        assert_eq!(store.remove(1), Some("a"));
+        assert_eq!(store.remove(1), None);
+        assert_eq!((store.get(2), store.get(3)), (Some(&"B"), Some(&"c")));
+        *store.get_mut(3).unwrap() = "C";
+        let mut pairs: Vec<(i32, &str)> = store.iter().map(|(id, v)| (id, *v)).collect();
+        pairs.sort_unstable();
+        assert_eq!(pairs, [(2, "B"), (3, "C")]);
+        assert_eq!(store.ids().len(), 2);
+    }
+
+    #[test]
+    fn stale_generation_is_rejected_after_slot_reuse() {
+        let mut registry = EntityStore::new();
+        let make = |id| Entity::new(id, Vec3::ZERO, Vec3::ONE, 1, 1);
+        let old = registry.insert(make);
+        registry.remove(old);
+        let new = registry.insert(make);
+        assert_ne!(old, new, "same slot, next generation");
+
+        let mut store = ComponentStore::new();
+        store.insert(old, 1.0);
+        assert!(!store.contains(new) && store.get(new).is_none() && store.remove(new).is_none());
+        // Новое поколение вытесняет мёртвый компонент прежнего
+        assert_eq!(store.insert(new, 2.0), None);
+        assert_eq!((store.get(old), store.get(new), store.len()), (None, Some(&2.0), 1));
+    }
+
+    #[test]
+    fn ids_with_intersects_stores() {
+        struct Hot;
+        struct Heavy(f32);
+        let mut components = Components::new();
+        assert!(components.ids_with::<(Hot,)>().is_empty(), "no store yet");
+        for id in 1..=4 { components.insert(id, Heavy(id as f32)); }
+        for id in [2, 4, 5] { components.insert(id, Hot); }
+        let mut both = components.ids_with::<(Heavy, Hot)>();
+        both.sort_unstable();
+        assert_eq!(both, [2, 4]);
+        assert!(components.ids_with::<(Heavy, Hot, u8)>().is_empty());
+
+        components.remove_entity(4);
+        assert_eq!(components.ids_with::<(Hot, Heavy)>(), [2]);
+        assert_eq!(components.get::<Heavy>(3).map(|h| h.0), Some(3.0));
+        components.clear();
+        assert!(components.owners().next().is_none());
+
+        // Мир отдаёт компоненты только живым сущностям и забывает их вместе с сущностью
+        let mut world = World::new();
+        let a = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1);
+        let b = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1);
+        assert!(world.insert_component(-1, Hot).is_err());
+        world.insert_component(a, Hot).unwrap();
+        world.insert_component(b, Hot).unwrap();
+        world.insert_component(b, Heavy(1.0)).unwrap();
+        assert_eq!(world.with::<(Heavy, Hot)>(), [b]);
+        assert!(world.remove_component::<Heavy>(b).is_some() && world.with::<(Heavy,)>().is_empty());
+        world.mark_for_deletion(a);
+        world.cleanup();
+        assert_eq!(world.with::<(Hot,)>(), [b]);
+    }
+}
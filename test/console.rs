@@ -0,0 +1,1036 @@
+// Построчная отладочная консоль над World. Читает команды из stdin или из файла сценария,
+// так что любой баг можно воспроизвести скриптом. Векторы пишутся без пробелов: x,y,z.
+use crate::Aabb;
+use crate::commands::{SpawnHandle, Target, WorldCommands};
+use crate::DynamicBvh;
+use crate::Vec3;
+use crate::entity::{Activity, BodyKind, DamageZone, Lifetime, RigidBody};
+use crate::events::Event;
+use crate::filter::QueryFilter;
+use crate::interaction::InteractionSystem;
+use crate::persistency;
+use crate::ray::Ray;
+use crate::validate::validate_world;
+use crate::world::World;
+use glam::{DVec3, EulerRot, Quat};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+pub const HELP: &str = "\
+commands:
+  spawn <pos> <size> <cat> <mask>   create entity, prints its id
+  move <id> <pos>                   move entity
+  spawn-at <world> <size> <cat> <mask>   create entity at an exact world position (f64), see origin
+  place <id> <world>                move entity to an exact world position
+  origin <offset>                   shift the floating origin by offset, keeping entities in place in the world
+  delete <id>                       mark for deletion and clean up, children included
+  body <id> static|dynamic|kinematic   move entity between the static and the dynamic tree
+  build-static                      rebuild the static tree with SAH
+  activity <id> awake|sleeping|disabled   sleeping entities skip trigger tests, disabled ones are invisible to queries
+  sleep-after <steps>               idle steps before an entity falls asleep (0 — never)
+  attach <child> <parent>           make child follow parent, keeping its place in the world
+  detach <id>                       detach from the parent
+  local <id> <pos> [degrees]        place a child relative to its parent (tree updated on flush/step)
+  resize <id> <size>                change entity size (tree updated on flush/step)
+  set-layers <id> <cat> <mask>      change entity layers
+  rotate <id> <degrees>             rotate entity by x,y,z euler angles (0,0,0 — axis-aligned)
+  margin <id> <margin>              fat-AABB margin of the entity's tree leaf (next reinsert)
+  flush                             apply pending size/layer/rotation changes to the tree
+  info <id>                         entity position, layers, health and components
+  damage <id> <amount>              hurt an entity, prints remaining health
+  blast <center> <radius> <amount> [as]  damage everything touching the sphere
+  zone <id> <per-second>            make the entity a damage-over-time volume
+  remove <id> lifetime|zone|rigid   take a component off the entity
+  with <component> [component] [component]   entities that have all the listed components (lifetime, zone, rigid)
+  on-trigger <id> <handler>         call the handler when something enters the entity
+  on-interact <id> <handler>        call the handler when an actor interacts with the entity
+  on-expire <id> <handler>          call the handler when the entity's lifetime runs out
+  interact <actor> <target>         queue an interaction event
+  look <actor> <dir>                what the actor would interact with looking along dir
+  use <actor> <dir>                 interact with that target and run its handler
+  events                            deliver queued events to handlers and print them
+  listen <handler>                  call the handler for every event, not only its entity's own
+  defer spawn|move|resize|layers|rotate|despawn <args>   record a change without applying it; @N is the N-th deferred spawn
+  pending                           list deferred changes
+  apply                             apply deferred changes in one batch
+  lifetime <id> <seconds>           despawn the entity after this much simulated time
+  step <dt> [count]                 advance simulation time, prints expired and killed entities
+  rigid <id> <mass> [restitution] [friction]   make the entity a rigid body (mass 0 — immovable)
+  velocity <id> <vel>               set rigid body velocity
+  gravity <vec>                     gravity for rigid bodies
+  physics <dt> [count]              physics in fixed steps, then step — one game frame per count
+  query <min> <max> [as]            entities overlapping the box
+  exclude <layers>                  layers query, ray and blast always skip (0 — none)
+  include sleeping|disabled <on|off>   whether query, ray and blast see them (default: sleeping on, disabled off)
+  ray <origin> <dir> [as]           entities hit by the ray, nearest first
+  contacts                          overlapping pairs with normal, depth, push-out of the second and contact points
+  layers                            collision layers and what each one sees
+  layer <name>                      add a collision layer
+  collide <a> <b> <on|off> [oneway] change the collision matrix
+  stats                             entity/tree counters
+  validate                          check tree and registry invariants
+  save <file>                       save world as JSON
+  load <file>                       replace world from JSON
+  dump-tree                         print BVH nodes
+  clear                             remove all entities and deferred commands, keep layers and handlers
+  help                              this text
+  quit                              stop reading input
+vectors are written as x,y,z (no spaces); '#' starts a comment
+layers are names or numbers joined with '|', e.g. static|trigger;
+[as] is an entity id or <cat>:<mask> to query on behalf of (default: no layer filter)
+built-in handlers: log, despawn-self, kill-other";
+
+#[derive(Debug)]
+pub enum Outcome {
+    Continue,
+    Quit,
+}
+
+// Разбор строки на токены: пробелы разделяют, "..." группирует, # до конца строки — комментарий
+pub fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut tok = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(ch) => tok.push(ch),
+                    None => return Err("unterminated quote".to_string()),
+                }
+            }
+            tokens.push(tok);
+        } else {
+            let mut tok = String::new();
+            while let Some(&ch) = chars.peek() {
+                if ch.is_whitespace() || ch == '#' { break; }
+                tok.push(ch);
+                chars.next();
+            }
+            tokens.push(tok);
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_xyz<T: std::str::FromStr + Default + Copy>(tok: &str) -> Result<[T; 3], String> {
+    let parts: Vec<&str> = tok.split(',').collect();
+    if parts.len() != 3 {
+        return Err(format!("expected vector x,y,z, got \"{}\"", tok));
+    }
+    let mut v = [T::default(); 3];
+    for (i, p) in parts.iter().enumerate() {
+        v[i] = p.parse().map_err(|_| format!("bad number \"{}\" in vector \"{}\"", p, tok))?;
+    }
+    Ok(v)
+}
+
+fn parse_vec3(tok: &str) -> Result<Vec3, String> {
+    parse_xyz(tok).map(Vec3::from_array)
+}
+
+// Точка мира в f64 — для координат больших уровней, см. World::origin
+fn parse_dvec3(tok: &str) -> Result<DVec3, String> {
+    parse_xyz(tok).map(DVec3::from_array)
+}
+
+// Углы Эйлера в градусах (x, y, z); нулевые — без поворота
+fn parse_rotation(tok: &str) -> Result<Option<Quat>, String> {
+    let deg = parse_vec3(tok)?;
+    if deg == Vec3::ZERO { return Ok(None); }
+    Ok(Some(Quat::from_euler(EulerRot::XYZ, deg.x.to_radians(), deg.y.to_radians(), deg.z.to_radians())))
+}
+
+fn parse_i32(tok: &str) -> Result<i32, String> {
+    tok.parse().map_err(|_| format!("expected integer, got \"{}\"", tok))
+}
+
+fn parse_f32(tok: &str) -> Result<f32, String> {
+    tok.parse().map_err(|_| format!("expected number, got \"{}\"", tok))
+}
+
+fn parse_on_off(tok: &str) -> Result<bool, String> {
+    match tok {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        other => Err(format!("expected on or off, got \"{}\"", other)),
+    }
+}
+
+// Урон не бывает отрицательным: лечить уроном нельзя
+fn parse_amount(tok: &str) -> Result<f32, String> {
+    let amount = parse_f32(tok)?;
+    if amount < 0.0 { return Err(format!("damage must not be negative: {}", tok)); }
+    Ok(amount)
+}
+
+// Число аргументов по строке usage; [x] — необязательный хвостовой аргумент
+fn args<'a>(tokens: &'a [String], usage: &str) -> Result<&'a [String], String> {
+    let words: Vec<&str> = usage.split_whitespace().skip(1).collect();
+    let required = words.iter().filter(|w| !w.starts_with('[')).count();
+    if tokens.len() - 1 < required || tokens.len() - 1 > words.len() {
+        return Err(format!("usage: {}", usage));
+    }
+    Ok(&tokens[1..])
+}
+
+fn join_ids(ids: &[i32]) -> String {
+    ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+// Сводка по миру и дереву — для команды stats и для CLI
+pub fn stats_report(world: &World) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "entities: {}, time: {:.3}", world.registry.len(), world.time);
+    let _ = writeln!(out, "pending deletion: {}, pending tree updates: {}", world.que_delete.len(), world.dirty.len());
+    let count = |a: Activity| world.registry.iter().filter(|e| e.activity == a).count();
+    let _ = writeln!(out, "awake: {}, sleeping: {}, disabled: {}", count(Activity::Awake), count(Activity::Sleeping), count(Activity::Disabled));
+    let _ = writeln!(out, "events queued: {}, dropped: {}", world.events.pending().len(), world.events.dropped);
+    let _ = writeln!(
+        out,
+        "components: lifetime {}, zone {}, rigid {}",
+        world.components.store::<Lifetime>().map_or(0, |s| s.len()),
+        world.components.store::<DamageZone>().map_or(0, |s| s.len()),
+        world.components.store::<RigidBody>().map_or(0, |s| s.len())
+    );
+    let _ = writeln!(out, "physics: step {:.4}, accumulated {:.4}, dropped {:.3} s", world.physics_dt, world.physics_time, world.physics_dropped);
+    for (name, bvh) in world.trees() {
+        let height = if bvh.root == -1 { 0 } else { bvh.nodes[bvh.root as usize].height };
+        let _ = writeln!(out, "{} tree: {} nodes allocated, {} proxies, height {}, sah {:.3}", name, bvh.nodes.len(), bvh.proxy_count, height, bvh.sah_cost());
+    }
+    // Статику не двигают, её счётчики неинтересны
+    let bvh = &world.bvh;
+    let _ = writeln!(
+        out,
+        "dynamic moves: {}, reinserts: {}, avoided: {:.0}%, refits: {}, rebuilds: {}",
+        bvh.stats.moves,
+        bvh.stats.reinserts,
+        bvh.stats.avoided_ratio() * 100.0,
+        bvh.stats.refits,
+        bvh.stats.rebuilds
+    );
+    out
+}
+
+pub struct Console {
+    pub world: World,
+    pub pending: WorldCommands, // команды defer, ждущие apply
+    pub exclude: i32,           // слои, которые query/ray/blast отбрасывают всегда, см. команду exclude
+    pub sleeping: bool,         // видят ли они спящих и отключённых, см. команду include
+    pub disabled: bool,
+}
+
+impl Console {
+    pub fn new(world: World) -> Self {
+        let mut console = Self { world, pending: WorldCommands::new(), exclude: 0, sleeping: true, disabled: false };
+        console.register_handlers();
+        console
+    }
+
+    // Встроенные обработчики для on-trigger/on-interact/on-expire; после load их нужно зарегистрировать заново
+    fn register_handlers(&mut self) {
+        let events = &mut self.world.events;
+        // Ничего не делает: событие и так видно в выводе команды events
+        events.register("log", |_, _| {});
+        // Удаляет саму сущность-владельца обработчика (подобранный предмет, одноразовый триггер)
+        events.register("despawn-self", |world, event| {
+            let id = match *event {
+                Event::Triggered { trigger, .. } => trigger,
+                Event::Interacted { target, .. } => target,
+                _ => return,
+            };
+            world.mark_for_deletion(id);
+            world.cleanup();
+        });
+        // Убивает вошедшего в триггер или того, кто взаимодействует
+        events.register("kill-other", |world, event| {
+            let id = match *event {
+                Event::Triggered { other, .. } => other,
+                Event::Interacted { actor, .. } => actor,
+                _ => return,
+            };
+            let hp = world.registry.get(id).map_or(0.0, |e| e.gameplay.health);
+            world.apply_damage(id, hp);
+            world.cleanup();
+        });
+    }
+
+    fn entity(&self, tok: &str) -> Result<i32, String> {
+        let id = parse_i32(tok)?;
+        if !self.world.registry.contains(id) {
+            return Err(format!("no entity {}", id));
+        }
+        Ok(id)
+    }
+
+    // Для defer: @N — ещё не созданная сущность из N-го отложенного spawn
+    fn target(&self, tok: &str) -> Result<Target, String> {
+        let Some(n) = tok.strip_prefix('@') else { return self.entity(tok).map(Target::Id) };
+        let n: usize = n.parse().map_err(|_| format!("bad spawn handle: {}", tok))?;
+        if n >= self.pending.spawns() {
+            return Err(format!("no deferred spawn {}", tok));
+        }
+        Ok(Target::Spawned(SpawnHandle(n)))
+    }
+
+    // Died из очереди событий: команда сообщает о тех, кого не было до неё, а сама очередь остаётся для events
+    fn died(&self) -> Vec<i32> {
+        self.world.events.pending().iter()
+            .filter_map(|e| match *e { Event::Died { id } => Some(id), _ => None })
+            .collect()
+    }
+
+    fn died_since(&self, before: &[i32]) -> Vec<i32> {
+        let mut killed: Vec<i32> = self.died().into_iter().filter(|id| !before.contains(id)).collect();
+        killed.sort_unstable();
+        killed
+    }
+
+    fn filter(&self, tok: Option<&String>) -> Result<QueryFilter<'static>, String> {
+        let filter = match tok.map(|t| (t, t.split_once(':'))) {
+            None => QueryFilter::any(),
+            Some((_, Some((cat, mask)))) => QueryFilter::any().layers(self.world.layers.parse_mask(cat)?, self.world.layers.parse_mask(mask)?),
+            Some((id, None)) => self.world.filter_for(self.entity(id)?),
+        };
+        Ok(filter.exclude(self.exclude).sleeping(self.sleeping).disabled(self.disabled))
+    }
+
+    // Выполняет одну строку; текст результата дописывается в out
+    pub fn execute(&mut self, line: &str, out: &mut String) -> Result<Outcome, String> {
+        let tokens = tokenize(line)?;
+        let Some(cmd) = tokens.first() else { return Ok(Outcome::Continue) };
+
+        match cmd.as_str() {
+            "spawn" => {
+                let a = args(&tokens, "spawn <pos> <size> <cat> <mask>")?;
+                let (pos, size) = (parse_vec3(&a[0])?, parse_vec3(&a[1])?);
+                if size.cmplt(Vec3::ZERO).any() {
+                    return Err(format!("size must not be negative: {}", a[1]));
+                }
+                let (cat, mask) = (self.world.layers.parse_mask(&a[2])?, self.world.layers.parse_mask(&a[3])?);
+                let id = self.world.create_entity(pos, size, cat, mask);
+                let _ = writeln!(out, "spawned {}", id);
+            }
+            "move" => {
+                let a = args(&tokens, "move <id> <pos>")?;
+                let id = self.entity(&a[0])?;
+                self.world.update_position(id, parse_vec3(&a[1])?);
+            }
+            "spawn-at" => {
+                let a = args(&tokens, "spawn-at <world> <size> <cat> <mask>")?;
+                let (pos, size) = (parse_dvec3(&a[0])?, parse_vec3(&a[1])?);
+                if size.cmplt(Vec3::ZERO).any() {
+                    return Err(format!("size must not be negative: {}", a[1]));
+                }
+                let (cat, mask) = (self.world.layers.parse_mask(&a[2])?, self.world.layers.parse_mask(&a[3])?);
+                let id = self.world.create_entity_at(pos, size, cat, mask);
+                let _ = writeln!(out, "spawned {}", id);
+            }
+            "place" => {
+                let a = args(&tokens, "place <id> <world>")?;
+                let id = self.entity(&a[0])?;
+                self.world.set_world_position(id, parse_dvec3(&a[1])?);
+            }
+            "origin" => {
+                let a = args(&tokens, "origin <offset>")?;
+                self.world.shift_origin(parse_vec3(&a[0])?);
+                let _ = writeln!(out, "origin {}", self.world.origin);
+            }
+            "resize" => {
+                let a = args(&tokens, "resize <id> <size>")?;
+                let id = self.entity(&a[0])?;
+                let size = parse_vec3(&a[1])?;
+                if size.cmplt(Vec3::ZERO).any() {
+                    return Err(format!("size must not be negative: {}", a[1]));
+                }
+                self.world.set_size(id, size);
+            }
+            "set-layers" => {
+                let a = args(&tokens, "set-layers <id> <cat> <mask>")?;
+                let id = self.entity(&a[0])?;
+                let (cat, mask) = (self.world.layers.parse_mask(&a[1])?, self.world.layers.parse_mask(&a[2])?);
+                self.world.set_layers(id, cat, mask);
+            }
+            "rotate" => {
+                let a = args(&tokens, "rotate <id> <degrees>")?;
+                let id = self.entity(&a[0])?;
+                self.world.set_rotation(id, parse_rotation(&a[1])?);
+            }
+            "margin" => {
+                let a = args(&tokens, "margin <id> <margin>")?;
+                let id = self.entity(&a[0])?;
+                let margin = parse_f32(&a[1])?;
+                if margin < 0.0 {
+                    return Err(format!("margin must not be negative: {}", a[1]));
+                }
+                self.world.set_margin(id, margin);
+            }
+            "flush" => {
+                args(&tokens, "flush")?;
+                let n = self.world.dirty.len();
+                let strategy = self.world.flush();
+                let _ = writeln!(out, "flushed {} entities ({:?})", n, strategy);
+            }
+            "delete" => {
+                let a = args(&tokens, "delete <id>")?;
+                let id = self.entity(&a[0])?;
+                let subtree = self.world.descendants(id);
+                self.world.mark_for_deletion(id);
+                self.world.cleanup();
+                if subtree.is_empty() {
+                    let _ = writeln!(out, "deleted {}", id);
+                } else {
+                    let _ = writeln!(out, "deleted {} with children {}", id, join_ids(&subtree));
+                }
+            }
+            "body" => {
+                let a = args(&tokens, "body <id> <static|dynamic|kinematic>")?;
+                let id = self.entity(&a[0])?;
+                let body = BodyKind::parse(&a[1]).ok_or(format!("unknown body kind \"{}\"", a[1]))?;
+                self.world.set_body(id, body);
+            }
+            "activity" => {
+                let a = args(&tokens, "activity <id> <awake|sleeping|disabled>")?;
+                let id = self.entity(&a[0])?;
+                let activity = Activity::parse(&a[1]).ok_or(format!("unknown activity \"{}\"", a[1]))?;
+                self.world.set_activity(id, activity);
+            }
+            "sleep-after" => {
+                let a = args(&tokens, "sleep-after <steps>")?;
+                self.world.sleep_after = a[0].parse().map_err(|_| format!("expected step count, got \"{}\"", a[0]))?;
+            }
+            "build-static" => {
+                args(&tokens, "build-static")?;
+                self.world.build_static();
+                let _ = writeln!(out, "static tree: {} proxies, sah {:.3}", self.world.static_bvh.proxy_count, self.world.static_bvh.sah_cost());
+            }
+            "attach" => {
+                let a = args(&tokens, "attach <child> <parent>")?;
+                let (child, parent) = (self.entity(&a[0])?, self.entity(&a[1])?);
+                self.world.set_parent(child, parent)?;
+            }
+            "detach" => {
+                let a = args(&tokens, "detach <id>")?;
+                let id = self.entity(&a[0])?;
+                if !self.world.detach(id) {
+                    return Err(format!("entity {} has no parent", id));
+                }
+            }
+            "local" => {
+                let a = args(&tokens, "local <id> <pos> [degrees]")?;
+                let id = self.entity(&a[0])?;
+                let rotation = match a.get(2) { Some(deg) => parse_rotation(deg)?, None => None };
+                if !self.world.set_local_transform(id, parse_vec3(&a[1])?, rotation) {
+                    return Err(format!("entity {} has no parent", id));
+                }
+            }
+            "on-trigger" | "on-interact" | "on-expire" => {
+                let a = args(&tokens, &format!("{} <id> <handler>", cmd))?;
+                let id = self.entity(&a[0])?;
+                if !self.world.events.is_registered(&a[1]) {
+                    return Err(format!("unknown handler \"{}\"", a[1]));
+                }
+                let e = &mut self.world.registry[id];
+                let slot = match cmd.as_str() {
+                    "on-trigger" => &mut e.on_trigger,
+                    "on-interact" => &mut e.on_interact,
+                    _ => &mut e.on_expire,
+                };
+                *slot = Some(a[1].clone());
+            }
+            "interact" => {
+                let a = args(&tokens, "interact <actor> <target>")?;
+                let (actor, target) = (self.entity(&a[0])?, self.entity(&a[1])?);
+                self.world.interact(actor, target);
+            }
+            "look" | "use" => {
+                let a = args(&tokens, &format!("{} <actor> <dir>", cmd))?;
+                let actor = self.entity(&a[0])?;
+                let (eye, dir) = (self.world.registry[actor].pos, parse_vec3(&a[1])?);
+                let system = InteractionSystem::new(&self.world);
+                let target = if cmd == "use" {
+                    system.interact(&mut self.world, actor, eye, dir)
+                } else {
+                    system.find_target(&self.world, actor, eye, dir)
+                };
+                match target {
+                    Some(t) => {
+                        let how = if t.by_ray { "in sight" } else { "nearby" };
+                        let _ = writeln!(out, "target {} ({}) {} at {:.2}", t.id, t.handler, how, t.distance);
+                    }
+                    None => { let _ = writeln!(out, "nothing to interact with"); }
+                }
+            }
+            "defer" => {
+                let Some(sub) = tokens.get(1) else { return Err("usage: defer <spawn|move|resize|layers|rotate|despawn> <args>".to_string()) };
+                let rest = &tokens[1..];
+                let layers = &self.world.layers;
+                match sub.as_str() {
+                    "spawn" => {
+                        let a = args(rest, "spawn <pos> <size> <cat> <mask>")?;
+                        let h = self.pending.spawn(parse_vec3(&a[0])?, parse_vec3(&a[1])?, layers.parse_mask(&a[2])?, layers.parse_mask(&a[3])?);
+                        let _ = writeln!(out, "spawn @{} deferred", h.0);
+                    }
+                    "move" => {
+                        let a = args(rest, "move <id> <pos>")?;
+                        self.pending.move_to(self.target(&a[0])?, parse_vec3(&a[1])?);
+                    }
+                    "resize" => {
+                        let a = args(rest, "resize <id> <size>")?;
+                        let size = parse_vec3(&a[1])?;
+                        if size.cmplt(Vec3::ZERO).any() {
+                            return Err(format!("size must not be negative: {}", a[1]));
+                        }
+                        self.pending.resize(self.target(&a[0])?, size);
+                    }
+                    "layers" => {
+                        let a = args(rest, "layers <id> <cat> <mask>")?;
+                        self.pending.set_layers(self.target(&a[0])?, layers.parse_mask(&a[1])?, layers.parse_mask(&a[2])?);
+                    }
+                    "rotate" => {
+                        let a = args(rest, "rotate <id> <degrees>")?;
+                        self.pending.rotate(self.target(&a[0])?, parse_rotation(&a[1])?);
+                    }
+                    "despawn" => {
+                        let a = args(rest, "despawn <id>")?;
+                        self.pending.despawn(self.target(&a[0])?);
+                    }
+                    other => return Err(format!("cannot defer \"{}\"", other)),
+                }
+            }
+            "pending" => {
+                args(&tokens, "pending")?;
+                for c in self.pending.commands() {
+                    let _ = writeln!(out, "{:?}", c);
+                }
+            }
+            "apply" => {
+                args(&tokens, "apply")?;
+                if self.pending.is_empty() {
+                    let _ = writeln!(out, "nothing to apply");
+                    return Ok(Outcome::Continue);
+                }
+                let n = self.pending.len();
+                let applied = self.world.apply_commands(std::mem::take(&mut self.pending));
+                let _ = writeln!(out, "applied {} commands ({:?}), spawned: {}", n, applied.strategy, join_ids(&applied.spawned));
+            }
+            "events" => {
+                args(&tokens, "events")?;
+                for event in self.world.dispatch_events() {
+                    let _ = writeln!(out, "{:?}", event);
+                }
+            }
+            "listen" => {
+                let a = args(&tokens, "listen <handler>")?;
+                if !self.world.events.is_registered(&a[0]) {
+                    return Err(format!("unknown handler \"{}\"", a[0]));
+                }
+                self.world.events.listen(&a[0]);
+            }
+            "lifetime" => {
+                let a = args(&tokens, "lifetime <id> <seconds>")?;
+                let id = self.entity(&a[0])?;
+                let seconds = parse_f32(&a[1])?;
+                self.world.insert_component(id, Lifetime::new(seconds))?;
+            }
+            "step" => {
+                let a = args(&tokens, "step <dt> [count]")?;
+                let dt = parse_f32(&a[0])?;
+                let count = match a.get(1) { Some(n) => parse_i32(n)?, None => 1 };
+                let mut expired = Vec::new();
+                let before = self.died();
+                for _ in 0..count { expired.extend(self.world.step(dt)); }
+                let killed = self.died_since(&before);
+                let _ = writeln!(out, "t={:.3} expired: {} killed: {}", self.world.time, join_ids(&expired), join_ids(&killed));
+            }
+            "rigid" => {
+                let a = args(&tokens, "rigid <id> <mass> [restitution] [friction]")?;
+                let id = self.entity(&a[0])?;
+                let mut rb = RigidBody::new(parse_f32(&a[1])?);
+                if let Some(r) = a.get(2) { rb.restitution = parse_f32(r)?; }
+                if let Some(f) = a.get(3) { rb.friction = parse_f32(f)?; }
+                self.world.insert_component(id, rb)?;
+            }
+            "velocity" => {
+                let a = args(&tokens, "velocity <id> <vel>")?;
+                let id = self.entity(&a[0])?;
+                let velocity = parse_vec3(&a[1])?;
+                let rb = self.world.component_mut::<RigidBody>(id).ok_or(format!("entity {} is not a rigid body", id))?;
+                rb.velocity = velocity;
+                self.world.wake(id);
+            }
+            "gravity" => {
+                let a = args(&tokens, "gravity <vec>")?;
+                self.world.gravity = parse_vec3(&a[0])?;
+            }
+            "physics" => {
+                let a = args(&tokens, "physics <dt> [count]")?;
+                let dt = parse_f32(&a[0])?;
+                let count = match a.get(1) { Some(n) => parse_i32(n)?, None => 1 };
+                let (mut steps, mut expired) = (0, Vec::new());
+                let before = self.died();
+                for _ in 0..count {
+                    steps += self.world.step_physics(dt);
+                    expired.extend(self.world.step(dt));
+                }
+                let killed = self.died_since(&before);
+                let _ = writeln!(out, "t={:.3} physics steps: {} expired: {} killed: {}", self.world.time, steps, join_ids(&expired), join_ids(&killed));
+            }
+            "info" => {
+                let a = args(&tokens, "info <id>")?;
+                let id = self.entity(&a[0])?;
+                let e = &self.world.registry[id];
+                let layers = &self.world.layers;
+                let _ = write!(out, "entity {} ({}, {}): pos {} size {}", id, e.body.name(), e.activity.name(), e.pos, e.size);
+                if self.world.origin != DVec3::ZERO || e.world_pos.is_some() {
+                    let _ = write!(out, " world {}", e.world_pos.unwrap_or(self.world.to_world(e.pos)));
+                }
+                if let Some(q) = e.rotation {
+                    let (x, y, z) = q.to_euler(EulerRot::XYZ);
+                    let _ = write!(out, " rotation {:.1},{:.1},{:.1}", x.to_degrees(), y.to_degrees(), z.to_degrees());
+                }
+                let _ = writeln!(out);
+                let _ = writeln!(out, "  layers {} sees {}", layers.describe(e.category), layers.describe(e.mask));
+                let _ = write!(out, "  hp {:.2}", e.gameplay.health);
+                if e.gameplay.is_dirty { let _ = write!(out, " (pending deletion)"); }
+                if let Some(l) = self.world.component::<Lifetime>(id) { let _ = write!(out, ", lifetime {:.3}", l.remaining); }
+                if let Some(z) = self.world.component::<DamageZone>(id) { let _ = write!(out, ", zone {}/s", z.per_second); }
+                let _ = writeln!(out);
+                if let Some(rb) = self.world.component::<RigidBody>(id) {
+                    let _ = writeln!(out, "  mass {} velocity {} restitution {} friction {}", rb.mass(), rb.velocity, rb.restitution, rb.friction);
+                }
+                if e.parent != -1 { let _ = writeln!(out, "  parent {} at local {}", e.parent, e.local_pos); }
+                if !e.children.is_empty() { let _ = writeln!(out, "  children {}", join_ids(&e.children)); }
+            }
+            "damage" => {
+                let a = args(&tokens, "damage <id> <amount>")?;
+                let id = self.entity(&a[0])?;
+                let hp = self.world.apply_damage(id, parse_amount(&a[1])?).unwrap();
+                let _ = writeln!(out, "entity {}: hp {:.2}{}", id, hp, if hp <= 0.0 { " (dead)" } else { "" });
+                self.world.cleanup();
+            }
+            "blast" => {
+                let a = args(&tokens, "blast <center> <radius> <amount> [as]")?;
+                let filter = self.filter(a.get(3))?;
+                let before = self.died();
+                let hits = self.world.apply_area_damage(parse_vec3(&a[0])?, parse_f32(&a[1])?, parse_amount(&a[2])?, &filter);
+                let killed = self.died_since(&before);
+                self.world.cleanup();
+                let _ = writeln!(out, "hit: {} killed: {}", join_ids(&hits), join_ids(&killed));
+            }
+            "zone" => {
+                let a = args(&tokens, "zone <id> <per-second>")?;
+                let id = self.entity(&a[0])?;
+                self.world.insert_component(id, DamageZone { per_second: parse_amount(&a[1])? })?;
+            }
+            "remove" => {
+                let a = args(&tokens, "remove <id> lifetime|zone|rigid")?;
+                let id = self.entity(&a[0])?;
+                let removed = match a[1].as_str() {
+                    "lifetime" => self.world.remove_component::<Lifetime>(id).is_some(),
+                    "zone" => self.world.remove_component::<DamageZone>(id).is_some(),
+                    "rigid" => self.world.remove_component::<RigidBody>(id).is_some(),
+                    other => return Err(format!("unknown component \"{}\"", other)),
+                };
+                if !removed { return Err(format!("entity {} has no {}", id, a[1])); }
+            }
+            "with" => {
+                let a = args(&tokens, "with <component> [component] [component]")?;
+                // Набор компонентов — битовая маска, пересечение считает World::with
+                let mut set = 0;
+                for name in a {
+                    set |= match name.as_str() {
+                        "lifetime" => 1,
+                        "zone" => 2,
+                        "rigid" => 4,
+                        other => return Err(format!("unknown component \"{}\"", other)),
+                    };
+                }
+                let w = &self.world;
+                let mut ids = match set {
+                    1 => w.with::<(Lifetime,)>(),
+                    2 => w.with::<(DamageZone,)>(),
+                    4 => w.with::<(RigidBody,)>(),
+                    3 => w.with::<(Lifetime, DamageZone)>(),
+                    5 => w.with::<(Lifetime, RigidBody)>(),
+                    6 => w.with::<(DamageZone, RigidBody)>(),
+                    _ => w.with::<(Lifetime, DamageZone, RigidBody)>(),
+                };
+                ids.sort_unstable();
+                let _ = writeln!(out, "{}", join_ids(&ids));
+            }
+            "exclude" => {
+                let a = args(&tokens, "exclude <layers>")?;
+                self.exclude = self.world.layers.parse_mask(&a[0])?;
+            }
+            "include" => {
+                let a = args(&tokens, "include sleeping|disabled <on|off>")?;
+                let on = parse_on_off(&a[1])?;
+                match a[0].as_str() {
+                    "sleeping" => self.sleeping = on,
+                    "disabled" => self.disabled = on,
+                    other => return Err(format!("expected sleeping or disabled, got \"{}\"", other)),
+                }
+            }
+            "query" => {
+                let a = args(&tokens, "query <min> <max> [as]")?;
+                let bbox = Aabb::new(parse_vec3(&a[0])?, parse_vec3(&a[1])?);
+                let filter = self.filter(a.get(2))?;
+                let mut hits = Vec::new();
+                self.world.query(&bbox, &filter, &mut hits);
+                hits.sort_unstable();
+                let _ = writeln!(out, "{} hits: {}", hits.len(), join_ids(&hits));
+            }
+            "ray" => {
+                let a = args(&tokens, "ray <origin> <dir> [as]")?;
+                let dir = parse_vec3(&a[1])?;
+                if dir == Vec3::ZERO {
+                    return Err("ray direction must not be zero".to_string());
+                }
+                let ray = Ray::new(parse_vec3(&a[0])?, dir);
+                let hits = self.world.ray_hits(&ray, &self.filter(a.get(2))?);
+                let _ = write!(out, "{} hits:", hits.len());
+                for (t, id) in hits {
+                    let _ = write!(out, " {}@{:.3}", id, t);
+                }
+                let _ = writeln!(out);
+            }
+            "contacts" => {
+                args(&tokens, "contacts")?;
+                let contacts = self.world.contacts();
+                let _ = writeln!(out, "{} contacts", contacts.len());
+                for c in contacts {
+                    let m = &c.manifold;
+                    let _ = write!(out, "{} {}: normal {} depth {:.3} push {} points", c.a, c.b, m.normal, m.depth, m.mtv());
+                    for p in &m.points {
+                        let _ = write!(out, " {}", p);
+                    }
+                    let _ = writeln!(out);
+                }
+            }
+            "layers" => {
+                args(&tokens, "layers")?;
+                let layers = &self.world.layers;
+                for name in layers.names() {
+                    let bit = layers.bit(name).unwrap();
+                    let _ = writeln!(out, "{:>10} = {:<6} sees {}", name, bit, layers.describe(layers.row(bit)));
+                }
+            }
+            "layer" => {
+                let a = args(&tokens, "layer <name>")?;
+                let bit = self.world.layers.add(&a[0])?;
+                let _ = writeln!(out, "{} = {}", a[0], bit);
+            }
+            "collide" => {
+                let a = args(&tokens, "collide <a> <b> <on|off> [oneway]")?;
+                let (x, y) = (self.world.layers.parse_mask(&a[0])?, self.world.layers.parse_mask(&a[1])?);
+                let on = parse_on_off(&a[2])?;
+                match a.get(3).map(|s| s.as_str()) {
+                    None => self.world.layers.set_collides(x, y, on),
+                    Some("oneway") => self.world.layers.set_sees(x, y, on),
+                    Some(other) => return Err(format!("expected oneway, got \"{}\"", other)),
+                }
+            }
+            "stats" => {
+                args(&tokens, "stats")?;
+                out.push_str(&stats_report(&self.world));
+            }
+            "validate" => {
+                args(&tokens, "validate")?;
+                validate_world(&self.world)?;
+                let _ = writeln!(out, "ok");
+            }
+            "save" => {
+                let a = args(&tokens, "save <file>")?;
+                persistency::save_world(&self.world, Path::new(&a[0]))?;
+                let _ = writeln!(out, "saved {} entities to {}", self.world.registry.len(), a[0]);
+            }
+            "load" => {
+                let a = args(&tokens, "load <file>")?;
+                self.world = persistency::load_world(Path::new(&a[0]))?;
+                self.register_handlers();
+                let _ = writeln!(out, "loaded {} entities from {}", self.world.registry.len(), a[0]);
+            }
+            "dump-tree" => {
+                args(&tokens, "dump-tree")?;
+                self.dump_tree(out);
+            }
+            "clear" => {
+                args(&tokens, "clear")?;
+                self.world.clear_all();
+                self.pending = WorldCommands::new();
+            }
+            "help" => {
+                let _ = writeln!(out, "{}", HELP);
+            }
+            "quit" | "exit" => return Ok(Outcome::Quit),
+            other => return Err(format!("unknown command \"{}\" (try help)", other)),
+        }
+        Ok(Outcome::Continue)
+    }
+
+    fn dump_tree(&self, out: &mut String) {
+        for (name, bvh) in self.world.trees() {
+            let _ = writeln!(out, "{}:", name);
+            Self::dump_nodes(bvh, out);
+        }
+    }
+
+    fn dump_nodes(bvh: &DynamicBvh, out: &mut String) {
+        if bvh.root == -1 {
+            let _ = writeln!(out, "(empty)");
+            return;
+        }
+        let mut stack = vec![(bvh.root, 0)];
+        while let Some((idx, depth)) = stack.pop() {
+            let node = &bvh.nodes[idx as usize];
+            let (min, max) = (node.bbox.min, node.bbox.max);
+            let _ = write!(
+                out,
+                "{:indent$}#{} [{:.2},{:.2},{:.2} .. {:.2},{:.2},{:.2}] ",
+                "", idx, min.x, min.y, min.z, max.x, max.y, max.z,
+                indent = depth * 2
+            );
+            if node.is_leaf {
+                let _ = writeln!(out, "leaf -> entity {}", node.object_index);
+            } else {
+                let _ = writeln!(out, "h={}", node.height);
+                stack.push((node.child2, depth + 1));
+                stack.push((node.child1, depth + 1));
+            }
+        }
+    }
+
+    // Читает команды построчно. В режиме сценария (echo) каждая команда печатается перед результатом,
+    // чтобы лог можно было сравнивать между прогонами. Возвращает число строк с ошибками.
+    pub fn run(&mut self, input: impl BufRead, output: &mut impl Write, prompt: bool, echo: bool) -> io::Result<usize> {
+        let mut errors = 0;
+        if prompt { write!(output, "> ")?; output.flush()?; }
+        for (n, line) in input.lines().enumerate() {
+            let line = line?;
+            if echo && !line.trim().is_empty() { writeln!(output, "> {}", line.trim())?; }
+
+            let mut out = String::new();
+            let result = self.execute(&line, &mut out);
+            output.write_all(out.as_bytes())?;
+            match result {
+                Ok(Outcome::Quit) => break,
+                Ok(Outcome::Continue) => {}
+                Err(msg) => {
+                    errors += 1;
+                    if echo { writeln!(output, "error: line {}: {}", n + 1, msg)?; } else { writeln!(output, "error: {}", msg)?; }
+                }
+            }
+            if prompt { write!(output, "> ")?; output.flush()?; }
+        }
+        Ok(errors)
+    }
+
+    pub fn run_script(&mut self, path: &Path, output: &mut impl Write) -> Result<usize, String> {
+        let file = std::fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.run(io::BufReader::new(file), output, false, true).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Console, Outcome, parse_rotation, parse_vec3, tokenize};
+    use crate::Vec3;
+    use crate::world::World;
+
+    fn run(script: &str) -> (String, usize) {
+        let mut console = Console::new(World::new());
+        let mut out = Vec::new();
+        let errors = console.run(script.as_bytes(), &mut out, false, true).unwrap();
+        (String::from_utf8(out).unwrap(), errors)
+    }
+
+    #[test]
+    fn tokenizer_quotes_and_comments() {
+        assert_eq!(tokenize(r#"  on-trigger 3 "kill other" # comment "#).unwrap(), ["on-trigger", "3", "kill other"]);
+        assert_eq!(tokenize("a#b c").unwrap(), ["a"]);
+        assert_eq!(tokenize(r#""" x"#).unwrap(), ["", "x"]);
+        assert!(tokenize("   # only a comment").unwrap().is_empty());
+        assert_eq!(tokenize(r#"say "unterminated"#).unwrap_err(), "unterminated quote");
+    }
+
+    #[test]
+    fn vector_and_rotation_parsing() {
+        assert_eq!(parse_vec3("1,-2.5,3e1").unwrap(), Vec3::new(1.0, -2.5, 30.0));
+        assert_eq!(parse_vec3("1,2").unwrap_err(), r#"expected vector x,y,z, got "1,2""#);
+        assert_eq!(parse_vec3("1,x,3").unwrap_err(), r#"bad number "x" in vector "1,x,3""#);
+        assert_eq!(parse_rotation("0,0,0").unwrap(), None);
+        let q = parse_rotation("0,90,0").unwrap().unwrap();
+        assert!((q * Vec3::X - Vec3::NEG_Z).length() < 1e-6);
+    }
+
+    #[test]
+    fn errors_name_the_usage_and_the_line() {
+        let mut console = Console::new(World::new());
+        let mut out = String::new();
+        assert_eq!(console.execute("move 1", &mut out).unwrap_err(), "usage: move <id> <pos>");
+        assert_eq!(console.execute("move 1 0,0,0 extra", &mut out).unwrap_err(), "usage: move <id> <pos>");
+        assert_eq!(console.execute("move 7 0,0,0", &mut out).unwrap_err(), "no entity 7");
+        assert_eq!(console.execute("frobnicate", &mut out).unwrap_err(), r#"unknown command "frobnicate" (try help)"#);
+        assert_eq!(console.execute("spawn 0,0,0 -1,1,1 1 1", &mut out).unwrap_err(), "size must not be negative: -1,1,1");
+        // Необязательные аргументы в [..] можно опускать
+        assert!(matches!(console.execute("step 0.5", &mut out), Ok(Outcome::Continue)));
+        assert!(out.contains("t=0.500"), "{}", out);
+
+        let (out, errors) = run("spawn 0,0,0 1,1,1 1 1\n\nmove 1 1,0,0\nmove 2 1,0,0\ninfo 1\nquit\ninfo 1\n");
+        assert_eq!(errors, 1);
+        assert!(out.contains("> move 2 1,0,0\nerror: line 4: no entity 2\n"), "{}", out);
+        assert!(out.contains("entity 1 (dynamic, awake): pos [1, 0, 0]"), "{}", out);
+        // После quit строки не читаются
+        assert_eq!(out.matches("> info 1").count(), 1);
+    }
+
+    #[test]
+    fn query_and_ray_output() {
+        let (out, errors) = run("\
+spawn 0,0,0 1,1,1 static static
+spawn 3,0,0 1,1,1 player static|player
+query -1,-1,-1 1,1,1
+ray -5,0,0 1,0,0
+ray -5,0,0 1,0,0 2
+ray 0,0,0 0,0,0
+exclude static
+ray -5,0,0 1,0,0
+query -1,-1,-1 4,1,1 static:static|player
+exclude 0
+activity 1 disabled
+activity 2 sleeping
+include sleeping off
+query -1,-1,-1 4,1,1
+include disabled on
+query -1,-1,-1 4,1,1
+include awake on
+");
+        assert_eq!(errors, 2);
+        assert!(out.contains("> query -1,-1,-1 1,1,1\n1 hits: 1\n"), "{}", out);
+        assert!(out.contains("> ray -5,0,0 1,0,0\n2 hits: 1@4.500 2@7.500\n"), "{}", out);
+        // От имени сущности 2: она сама не попадает в результаты
+        assert!(out.contains("> ray -5,0,0 1,0,0 2\n1 hits: 1@4.500\n"), "{}", out);
+        assert!(out.contains("error: line 6: ray direction must not be zero"), "{}", out);
+        assert!(out.contains("> exclude static\n> ray -5,0,0 1,0,0\n1 hits: 2@7.500\n"), "{}", out);
+        assert!(out.contains("> query -1,-1,-1 4,1,1 static:static|player\n1 hits: 2\n"), "{}", out);
+        assert!(out.contains("> include sleeping off\n> query -1,-1,-1 4,1,1\n0 hits: \n"), "{}", out);
+        assert!(out.contains("> include disabled on\n> query -1,-1,-1 4,1,1\n1 hits: 1\n"), "{}", out);
+        assert!(out.contains("expected sleeping or disabled, got \"awake\""), "{}", out);
+    }
+
+    #[test]
+    fn save_then_load_restores_the_world() {
+        let path = std::env::temp_dir().join(format!("bvh-console-{}-save.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let (out, errors) = run(&format!("\
+spawn 0,0,0 1,1,1 static static
+spawn 3,0,0 1,1,1 player static|player
+spawn 6,0,0 1,1,1 player player
+attach 3 2
+local 3 0,2,0
+lifetime 2 5
+save {path}
+delete 2
+query -10,-10,-10 10,10,10
+load {path}
+query -10,-10,-10 10,10,10
+info 3
+with lifetime
+"));
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(errors, 0, "{}", out);
+        assert!(out.contains(&format!("saved 3 entities to {}\n", path)), "{}", out);
+        // delete уносит и ребёнка
+        assert!(out.contains("> delete 2\ndeleted 2 with children 3\n> query -10,-10,-10 10,10,10\n1 hits: 1\n"), "{}", out);
+        assert!(out.contains(&format!("loaded 3 entities from {}\n> query -10,-10,-10 10,10,10\n3 hits: 1 2 3\n", path)), "{}", out);
+        assert!(out.contains("entity 3 (dynamic, awake): pos [3, 2, 0]"), "{}", out);
+        assert!(out.contains("  parent 2 at local [0, 2, 0]\n"), "{}", out);
+        assert!(out.contains("> with lifetime\n2\n"), "{}", out);
+    }
+
+    #[test]
+    fn dump_tree_prints_nodes_and_leaves() {
+        let (out, errors) = run("\
+dump-tree
+spawn 0,0,0 1,1,1 1 1
+spawn 3,0,0 1,1,1 1 1
+body 1 static
+dump-tree
+");
+        assert_eq!(errors, 0, "{}", out);
+        assert!(out.contains("> dump-tree\nstatic:\n(empty)\ndynamic:\n(empty)\n"), "{}", out);
+        // Статике запас не нужен: её лист — точный AABB
+        assert!(out.contains("\
+static:
+#0 [-0.50,-0.50,-0.50 .. 0.50,0.50,0.50] leaf -> entity 1
+dynamic:
+#1 [2.30,-0.70,-0.70 .. 3.70,0.70,0.70] leaf -> entity 2
+"), "{}", out);
+    }
+
+    #[test]
+    fn deferred_changes_wait_for_apply() {
+        let (out, errors) = run("\
+spawn 0,0,0 1,1,1 1 1
+apply
+defer spawn 9,0,0 1,1,1 1 1
+defer move @0 9,5,0
+defer move @1 0,0,0
+defer despawn 1
+query -10,-10,-10 10,10,10
+pending
+apply
+query -10,-10,-10 10,10,10
+info 2
+");
+        assert_eq!(errors, 1, "{}", out);
+        assert!(out.contains("> apply\nnothing to apply\n"), "{}", out);
+        assert!(out.contains("> defer spawn 9,0,0 1,1,1 1 1\nspawn @0 deferred\n"), "{}", out);
+        assert!(out.contains("error: line 5: no deferred spawn @1"), "{}", out);
+        // До apply мир не тронут
+        assert!(out.contains("> query -10,-10,-10 10,10,10\n1 hits: 1\n> pending\nSpawn"), "{}", out);
+        assert!(out.contains("Move { target: Spawned(SpawnHandle(0)), pos: Vec3(9.0, 5.0, 0.0) }\nDespawn { target: Id(1) }\n"), "{}", out);
+        assert!(out.contains("> apply\napplied 3 commands"), "{}", out);
+        assert!(out.contains("spawned: 2\n> query -10,-10,-10 10,10,10\n1 hits: 2\n"), "{}", out);
+        assert!(out.contains("entity 2 (dynamic, awake): pos [9, 5, 0]"), "{}", out);
+    }
+
+    #[test]
+    fn components_layers_and_stats() {
+        let (out, errors) = run("\
+spawn 0,0,0 1,1,1 player player
+spawn 0.5,0,0 1,1,1 player player
+zone 1 4
+rigid 2 1
+lifetime 2 5
+with zone
+with lifetime rigid
+with zone rigid
+contacts
+collide player player on
+contacts
+stats
+step 1
+info 2
+collide player player off
+layers
+contacts
+");
+        assert_eq!(errors, 0, "{}", out);
+        assert!(out.contains("> with zone\n1\n> with lifetime rigid\n2\n> with zone rigid\n\n"), "{}", out);
+        // Маску сущности фильтрует ещё и таблица слоёв: по умолчанию player не видит player
+        assert!(out.contains("> contacts\n0 contacts\n> collide player player on\n> contacts\n1 contacts\n1 2: "), "{}", out);
+        assert!(out.contains("components: lifetime 1, zone 1, rigid 1\n"), "{}", out);
+        assert!(out.contains("t=1.000 expired:  killed: \n"), "{}", out);
+        assert!(out.contains("  hp 96.00, lifetime 4.000\n"), "{}", out);
+        assert!(out.contains("    player = 4      sees static|trigger\n> contacts\n0 contacts\n"), "{}", out);
+    }
+}
@@ -0,0 +1,92 @@
+// Пары широкой фазы и контакты для них: кого выталкивать из стен и насколько.
+// Пару ищет только бодрствующий подвижный участник — статика со статикой и спящие между собой
+// не сталкиваются. Слои проверяются в обе стороны: достаточно, чтобы один видел другого.
+// Ребёнок не сталкивается со своими предками — оружие в руке не выталкивает игрока.
+// Контакт неповёрнутой пары строится по AABB; если повёрнут хоть один — по OBB обоих (SAT),
+// как и Entity::overlaps: плотный AABB повёрнутого больше самого тела и дал бы лишнюю глубину.
+use crate::entity::{Activity, BodyKind, Entity};
+use crate::filter::QueryFilter;
+use crate::geometry::{Manifold, aabb_manifold, obb_manifold};
+use crate::layers::LayerFilter;
+use crate::world::World;
+use std::collections::BTreeSet;
+
+// Контакт пары a < b: нормаль манифолда смотрит от a к b
+#[derive(Clone, Debug)]
+pub struct Contact {
+    pub a: i32,
+    pub b: i32,
+    pub manifold: Manifold,
+}
+
+#[rustfmt::skip]
+impl World {
+    fn sees_either(&self, a: &Entity, b: &Entity) -> bool {
+        LayerFilter::new(a.category, a.mask).accepts(&self.layers, b.category)
+            || LayerFilter::new(b.category, b.mask).accepts(&self.layers, a.category)
+    }
+    fn is_ancestor(&self, ancestor: i32, id: i32) -> bool {
+        let mut cur = self.parent_of(id);
+        while let Some(p) = cur {
+            if p == ancestor { return true; }
+            cur = self.parent_of(p);
+        }
+        false
+    }
+    // Пересекающиеся пары (a < b) без повторов, по возрастанию
+    pub fn broadphase_pairs(&self) -> Vec<(i32, i32)> {
+        let mut pairs = BTreeSet::new();
+        let mut hits = Vec::new();
+        for a in self.registry.iter() {
+            if a.body == BodyKind::Static || a.activity != Activity::Awake || a.gameplay.is_dirty { continue; }
+            hits.clear();
+            self.query(&a.get_aabb(), &QueryFilter::any().ignore(a.id).skip_dirty(), &mut hits);
+            for &id in &hits {
+                let b = &self.registry[id];
+                if !self.sees_either(a, b) || !a.overlaps(b) { continue; }
+                if self.is_ancestor(a.id, id) || self.is_ancestor(id, a.id) { continue; }
+                pairs.insert((a.id.min(id), a.id.max(id)));
+            }
+        }
+        pairs.into_iter().collect()
+    }
+    pub fn contacts(&self) -> Vec<Contact> {
+        self.broadphase_pairs().into_iter()
+            .filter_map(|(a, b)| {
+                let (ea, eb) = (&self.registry[a], &self.registry[b]);
+                let manifold = match (ea.rotation, eb.rotation) {
+                    (None, None) => aabb_manifold(&ea.get_aabb(), &eb.get_aabb())?,
+                    _ => obb_manifold(&ea.get_obb(), &eb.get_obb())?,
+                };
+                Some(Contact { a, b, manifold })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Vec3;
+    use crate::world::World;
+    use glam::Quat;
+
+    #[test]
+    fn rotated_pairs_touch_by_their_obb() {
+        let mut world = World::new();
+        // Ромб (куб, повёрнутый на 45°) и бокс у его грани; плотный AABB ромба залез бы в бокс на 0.914
+        let diamond = world.create_entity(Vec3::ZERO, Vec3::splat(2.0), 4, 1);
+        world.set_rotation(diamond, Some(Quat::from_rotation_y(std::f32::consts::FRAC_PI_4)));
+        let near = world.create_entity(Vec3::new(1.5, 0.0, 1.5), Vec3::splat(2.0), 1, 0);
+        // А этот касается только AABB ромба
+        world.create_entity(Vec3::new(-1.9, 0.0, -1.9), Vec3::splat(2.0), 1, 0);
+        world.flush();
+
+        let contacts = world.contacts();
+        assert_eq!(contacts.len(), 1);
+        let (c, diagonal) = (&contacts[0], Vec3::new(1.0, 0.0, 1.0).normalize());
+        assert_eq!((c.a, c.b), (diamond, near));
+        assert!((c.manifold.depth - (3.0 / 2f32.sqrt() - 1.0 - 2f32.sqrt()).abs()).abs() < 1e-4, "depth {}", c.manifold.depth);
+        assert!((c.manifold.normal - diagonal).length() < 1e-4, "normal {}", c.manifold.normal);
+        assert_eq!(c.manifold.points, [Vec3::new(0.5, -1.0, 0.5), Vec3::new(0.5, 1.0, 0.5)]);
+    }
+}
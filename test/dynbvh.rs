@@ -1,15 +1,69 @@
 use crate::Aabb;
 use crate::Stack;
+use crate::Vec3;
 use crate::node::Node;
 use crate::ray::Ray;
+#[derive(Default, Clone, Copy)]
+pub struct ProxyStats {
+    pub moves: u64,     // сдвинутые прокси (move_proxy и update_proxies)
+    pub reinserts: u64, // из них закончились перевставкой листа
+    pub refits: u64,
+    pub rebuilds: u64,
+}
+impl ProxyStats {
+    pub fn avoided(&self) -> u64 {
+        self.moves - self.reinserts
+    }
+    pub fn avoided_ratio(&self) -> f32 {
+        if self.moves == 0 { return 0.0; }
+        self.avoided() as f32 / self.moves as f32
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UpdateStrategy {
+    Unchanged, // сдвигов нет или все листья остались в своих боксах — дерево не трогали
+    Reinsert,  // move_proxy для каждого сдвинутого листа
+    Refit,     // боксы листов на месте + один проход refit
+    Rebuild,   // refit испортил дерево — пересборка по SAH
+}
+#[derive(Clone, Copy)]
+pub struct RefitPolicy {
+    pub reinsert_fraction: f32, // = 0.1; если сдвинулось меньше этой доли листьев — перевставляем по одному
+    pub max_sah_growth: f32,    // = 1.5; во сколько раз SAH может вырасти после refit до пересборки
+}
 pub struct DynamicBvh {
     pub nodes: Vec<Node>,
     pub root: i32,
-    pub free_list: i32, // Индекс первого свободного узла для переиспользования
-    pub margin: f32,    // = 0.2f;
+    pub free_list: i32,           // Индекс первого свободного узла для переиспользования
+    pub margin: f32,              // = 0.2f; запас по умолчанию для новых прокси
+    pub velocity_multiplier: f32, // = 2.0f; во сколько раз смещения за кадр вытягивать лист по направлению движения
+    pub proxy_count: i32,         // число листьев в дереве
+    pub refit_policy: RefitPolicy,
+    pub sah_reference: f32,       // SAH после последней пересборки или вставки/удаления листа (0 — замерить заново)
+    pub stats: ProxyStats,
 }
 #[rustfmt::skip]
 impl DynamicBvh {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            root: -1,
+            free_list: -1,
+            margin: 0.2,
+            velocity_multiplier: 2.0,
+            proxy_count: 0,
+            refit_policy: RefitPolicy { reinsert_fraction: 0.1, max_sah_growth: 1.5 },
+            sah_reference: 0.0,
+            stats: ProxyStats::default(),
+        }
+    }
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = -1;
+        self.free_list = -1;
+        self.proxy_count = 0;
+        self.sah_reference = 0.0;
+    }
     pub fn allocate_node(&mut self) -> i32 {
         if self.free_list == -1 {
             let idx = self.nodes.len() as i32;
@@ -28,7 +82,7 @@ impl DynamicBvh {
     // Вспомогательная функция для расчета стоимости
     fn calc_entry_cost(&self, node_idx: i32, leaf_bbox: &Aabb, leaf_area: f32) -> f32 {
         let node = &self.nodes[node_idx as usize];
-        let combined = Aabb::union(&node.bbox, &leaf_bbox);
+        let combined = Aabb::union(&node.bbox, leaf_bbox);
         if node.is_leaf {
             Aabb::area(&combined) + leaf_area
         } else {
@@ -44,11 +98,23 @@ impl DynamicBvh {
             node.is_leaf = true;
             node.height = 0;
             node.parent_index = -1;
+            node.margin = 0.0;
         }
+        self.link_leaf(leaf_idx);
+        self.proxy_count += 1;
+        leaf_idx
+    }
+    // Вставка уже выделенного листа (bbox задан) — общая часть insert_leaf и move_proxy
+    fn link_leaf(&mut self, leaf_idx: i32) {
+        let leaf_bbox = self.nodes[leaf_idx as usize].bbox;
+        let bbox = &leaf_bbox;
+        self.nodes[leaf_idx as usize].parent_index = -1;
+        // Топология сменилась — прежний эталон SAH к этому дереву больше не относится
+        self.sah_reference = 0.0;
 
-        if self.root == -1 { self.root = leaf_idx; return leaf_idx; }
+        if self.root == -1 { self.root = leaf_idx; return; }
         let mut index = self.root;
-        let leaf_area = Aabb::area(&bbox);
+        let leaf_area = Aabb::area(bbox);
 
         while !self.nodes[index as usize].is_leaf {
             let node = &self.nodes[index as usize];
@@ -101,13 +167,17 @@ impl DynamicBvh {
 
         // 4. Проход вверх для обновления BBox и балансировки
         self.sync_hierarchie(leaf_idx);
-
-        leaf_idx
     }
     pub fn remove_leaf(&mut self, index: i32) {
+        self.unlink_leaf(index);
+        self.free_node(index);
+        self.proxy_count -= 1;
+    }
+    // Отцепляет лист от дерева, не освобождая сам узел
+    fn unlink_leaf(&mut self, index: i32) {
+        self.sah_reference = 0.0;
         if index == self.root {
             self.root = -1;
-            self.free_node(index);
             return;
         }
 
@@ -123,7 +193,6 @@ impl DynamicBvh {
         if gp != -1 {
             // Подключаем брата (sib) напрямую к дедушке (gp)
             if self.nodes[gp as usize].child1 == p {
-                self.nodes[gp as usize].child2 = sib; // Ошибка была здесь: должен быть тот же слот
                 self.nodes[gp as usize].child1 = sib;
             } else {
                 self.nodes[gp as usize].child2 = sib;
@@ -136,7 +205,208 @@ impl DynamicBvh {
             self.nodes[sib as usize].parent_index = -1;
             self.free_node(p);
         }
-        self.free_node(index);
+    }
+    // Лист с "толстым" AABB: margin хранится в узле и используется при каждом move_proxy
+    pub fn create_proxy(&mut self, obj_idx: i32, bbox: &Aabb, margin: f32) -> i32 {
+        let r = Vec3::splat(margin);
+        let leaf_idx = self.insert_leaf(obj_idx, &Aabb::new(bbox.min - r, bbox.max + r));
+        self.nodes[leaf_idx as usize].margin = margin;
+        leaf_idx
+    }
+    pub fn set_margin(&mut self, proxy: i32, margin: f32) {
+        self.nodes[proxy as usize].margin = margin;
+    }
+    // "Толстый" AABB: bbox с запасом margin, вытянутый по смещению за кадр
+    fn fat_bbox(&self, proxy: i32, bbox: &Aabb, displacement: Vec3) -> Aabb {
+        let r = Vec3::splat(self.nodes[proxy as usize].margin);
+        let mut fat = Aabb::new(bbox.min - r, bbox.max + r);
+
+        let d = displacement * self.velocity_multiplier;
+        fat.min += d.min(Vec3::ZERO);
+        fat.max += d.max(Vec3::ZERO);
+        fat
+    }
+    // Лист ещё годится, если накрывает bbox и не раздут сверх меры
+    // (лист, оставшийся после быстрого движения, может быть огромным — тогда его всё же ужимаем)
+    fn leaf_fits(&self, proxy: i32, bbox: &Aabb, fat: &Aabb) -> bool {
+        let tree_bbox = self.nodes[proxy as usize].bbox;
+        let r = Vec3::splat(self.nodes[proxy as usize].margin * 4.0);
+        tree_bbox.contains(*bbox) && Aabb::new(fat.min - r, fat.max + r).contains(tree_bbox)
+    }
+    // Как b2DynamicTree::MoveProxy: если лист всё ещё годится — ничего не делаем.
+    // Иначе лист перевставляется (индекс прокси сохраняется) с запасом margin и вытянутым по смещению за кадр,
+    // чтобы при равномерном движении следующие кадры тоже не требовали перевставки.
+    pub fn move_proxy(&mut self, proxy: i32, bbox: &Aabb, displacement: Vec3) -> bool {
+        self.stats.moves += 1;
+        let fat = self.fat_bbox(proxy, bbox, displacement);
+        if self.leaf_fits(proxy, bbox, &fat) { return false; }
+
+        self.unlink_leaf(proxy);
+        self.nodes[proxy as usize].bbox = fat;
+        self.link_leaf(proxy);
+        self.stats.reinserts += 1;
+        true
+    }
+
+    // Пакетное обновление: сколько листьев сдвинулось и насколько испортилось дерево решают,
+    // что дешевле — перевставить каждый лист, пересчитать боксы (refit) или пересобрать дерево целиком.
+    pub fn update_proxies(&mut self, moved: &[(i32, Aabb, Vec3)]) -> UpdateStrategy {
+        if moved.is_empty() { return UpdateStrategy::Unchanged; }
+
+        if (moved.len() as f32) < self.refit_policy.reinsert_fraction * self.proxy_count as f32 {
+            for (proxy, bbox, displacement) in moved {
+                self.move_proxy(*proxy, bbox, *displacement);
+            }
+            return UpdateStrategy::Reinsert;
+        }
+
+        if self.sah_reference == 0.0 { self.sah_reference = self.sah_cost(); }
+
+        let mut changed = false;
+        for (proxy, bbox, displacement) in moved {
+            self.stats.moves += 1;
+            let fat = self.fat_bbox(*proxy, bbox, *displacement);
+            if !self.leaf_fits(*proxy, bbox, &fat) {
+                self.nodes[*proxy as usize].bbox = fat;
+                changed = true;
+            }
+        }
+        if !changed { return UpdateStrategy::Unchanged; }
+        self.refit();
+
+        if self.sah_cost() > self.sah_reference * self.refit_policy.max_sah_growth {
+            self.rebuild();
+            return UpdateStrategy::Rebuild;
+        }
+        UpdateStrategy::Refit
+    }
+
+    // Сдвиг всех боксов (и свободных узлов тоже — дешевле, чем выбирать) без изменения топологии
+    pub fn shift_origin(&mut self, offset: Vec3) {
+        for node in &mut self.nodes {
+            node.bbox.min -= offset;
+            node.bbox.max -= offset;
+        }
+    }
+
+    // Пересчёт боксов и высот всех внутренних узлов снизу вверх за один проход (post-order),
+    // без изменения топологии. Боксы листьев должны быть уже обновлены на месте.
+    pub fn refit(&mut self) {
+        self.stats.refits += 1;
+        if self.root == -1 { return; }
+
+        let mut stack = Stack::new();
+        stack.push((self.root, false));
+        while let Some((idx, children_done)) = stack.pop() {
+            let node = &self.nodes[idx as usize];
+            if node.is_leaf { continue; }
+            if children_done {
+                self.update_node(idx);
+            } else {
+                let (c1, c2) = (node.child1, node.child2);
+                stack.push((idx, true));
+                stack.push((c1, false));
+                stack.push((c2, false));
+            }
+        }
+    }
+
+    // Суммарная площадь внутренних узлов относительно корня — оценка стоимости обхода (SAH)
+    pub fn sah_cost(&self) -> f32 {
+        if self.root == -1 { return 0.0; }
+        let root_area = Aabb::area(&self.nodes[self.root as usize].bbox);
+        if root_area <= 0.0 { return 0.0; }
+
+        let mut total = 0.0;
+        let mut stack = Stack::new();
+        stack.push(self.root);
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx as usize];
+            if node.is_leaf { continue; }
+            total += Aabb::area(&node.bbox);
+            stack.push(node.child1);
+            stack.push(node.child2);
+        }
+        total / root_area
+    }
+
+    // Полная пересборка сверху вниз по SAH. Листья (и их индексы-прокси) сохраняются,
+    // все внутренние узлы освобождаются и строятся заново.
+    pub fn rebuild(&mut self) {
+        self.stats.rebuilds += 1;
+        self.sah_reference = 0.0;
+        if self.root == -1 { return; }
+
+        let mut leaves = Vec::with_capacity(self.proxy_count as usize);
+        let mut stack = Stack::new();
+        stack.push(self.root);
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx as usize];
+            if node.is_leaf {
+                leaves.push(idx);
+            } else {
+                let (c1, c2) = (node.child1, node.child2);
+                stack.push(c1);
+                stack.push(c2);
+                self.free_node(idx);
+            }
+        }
+
+        self.root = self.build_range(&mut leaves);
+        self.nodes[self.root as usize].parent_index = -1;
+        self.sah_reference = self.sah_cost();
+    }
+
+    fn build_range(&mut self, leaves: &mut [i32]) -> i32 {
+        if leaves.len() == 1 { return leaves[0]; }
+
+        // Ось разбиения — наибольший разброс центров
+        let center = |bvh: &Self, idx: i32| {
+            let b = &bvh.nodes[idx as usize].bbox;
+            (b.min + b.max) * 0.5
+        };
+        let mut cmin = Vec3::splat(f32::MAX);
+        let mut cmax = Vec3::splat(f32::MIN);
+        for &leaf in leaves.iter() {
+            let c = center(self, leaf);
+            cmin = cmin.min(c);
+            cmax = cmax.max(c);
+        }
+        let extent = cmax - cmin;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+        leaves.sort_by(|a, b| center(self, *a)[axis].total_cmp(&center(self, *b)[axis]));
+
+        // Перебор всех разрезов отсортированного списка: площадь левой части * число + правой * число
+        let n = leaves.len();
+        let mut right_area = vec![0.0; n];
+        let mut acc = self.nodes[leaves[n - 1] as usize].bbox;
+        for i in (1..n).rev() {
+            acc.merge(&self.nodes[leaves[i] as usize].bbox);
+            right_area[i] = Aabb::area(&acc);
+        }
+        let mut best = (f32::MAX, n / 2);
+        let mut acc = self.nodes[leaves[0] as usize].bbox;
+        for i in 1..n {
+            let cost = Aabb::area(&acc) * i as f32 + right_area[i] * (n - i) as f32;
+            if cost < best.0 { best = (cost, i); }
+            acc.merge(&self.nodes[leaves[i] as usize].bbox);
+        }
+
+        let (left, right) = leaves.split_at_mut(best.1);
+        let c1 = self.build_range(left);
+        let c2 = self.build_range(right);
+        let idx = self.allocate_node();
+        {
+            let node = &mut self.nodes[idx as usize];
+            node.child1 = c1;
+            node.child2 = c2;
+            node.is_leaf = false;
+            node.object_index = -1;
+        }
+        self.nodes[c1 as usize].parent_index = idx;
+        self.nodes[c2 as usize].parent_index = idx;
+        self.update_node(idx);
+        idx
     }
 
     pub fn sync_hierarchie(&mut self, index: i32) {
@@ -191,7 +461,7 @@ impl DynamicBvh {
             else {
                 self.nodes[r as usize].child2 = rr;
                 self.nodes[index as usize].child2 = rl;
-                self.nodes[rr as usize].parent_index = index;
+                self.nodes[rl as usize].parent_index = index;
             }
             self.update_node(index);
             self.update_node(r);
@@ -203,6 +473,7 @@ impl DynamicBvh {
             let lr=self.nodes[l as usize].child2;
             self.nodes[l as usize].child1 = index;
             self.nodes[l as usize].parent_index = self.nodes[index as usize].parent_index;
+            self.nodes[index as usize].parent_index = l;
             if self.nodes[l as usize].parent_index !=-1{
                 let parent_idx = self.nodes[l as usize].parent_index;
                 if self.nodes[parent_idx as usize].child1 == index {
@@ -221,7 +492,7 @@ impl DynamicBvh {
             else {
                 self.nodes[l as usize].child2 = lr;
                 self.nodes[index as usize].child1 = ll;
-                self.nodes[lr as usize].parent_index = index;
+                self.nodes[ll as usize].parent_index = index;
             }
             self.update_node(index);
             self.update_node(l);
@@ -231,7 +502,43 @@ impl DynamicBvh {
         index
     }
 
+    // accept вызывается на листе с object_index — отброшенные объекты не попадают в out
+    pub fn query_filtered(&self, bbox: &Aabb, accept: impl Fn(i32) -> bool, out: &mut Vec<i32>) {
+        if self.root == -1 { return; }
+
+        let mut stack = Stack::new();
+        stack.push(self.root);
+
+        while !stack.is_empty() {
+
+            let mut node_idx = 0;
+            if let Some(p) = stack.pop(){
+                node_idx=p;
+            };
+            let node = &self.nodes[node_idx as usize];
+
+            // Проверка на пересечение (Intersects), а не на удержание (Contains)
+            if node.bbox.min.x > bbox.max.x || node.bbox.max.x < bbox.min.x ||
+               node.bbox.min.y > bbox.max.y || node.bbox.max.y < bbox.min.y ||
+               node.bbox.min.z > bbox.max.z || node.bbox.max.z < bbox.min.z {
+                continue;
+            }
+
+            if node.is_leaf {
+                if accept(node.object_index) { out.push(node.object_index); }
+            } else {
+                // Всегда проверяйте переполнение стека, если дерево глубокое
+                stack.push(node.child1);
+                stack.push(node.child2);
+            }
+        }
+    }
+
     pub fn ray_cast(&self, ray: &Ray) -> Vec<i32> {
+        self.ray_cast_filtered(ray, |_| true)
+    }
+
+    pub fn ray_cast_filtered(&self, ray: &Ray, accept: impl Fn(i32) -> bool) -> Vec<i32> {
         let mut results = Vec::new();
         if self.root == -1 { return results; }
 
@@ -248,7 +555,7 @@ impl DynamicBvh {
             // Проверяем, пересекает ли луч текущий AABB (узел или лист)
             if node.bbox.intersect_ray(ray) {
                 if node.is_leaf {
-                    results.push(node.object_index);
+                    if accept(node.object_index) { results.push(node.object_index); }
                 } else {
                     // Добавляем детей в стек для дальнейшей проверки
                     // (для оптимизации можно сначала класть того, кто ближе к лучу)
@@ -260,3 +567,155 @@ impl DynamicBvh {
         results
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DynamicBvh, UpdateStrategy};
+    use crate::Aabb;
+    use crate::Vec3;
+    use crate::bvhcheck::validate_bvh;
+
+    // Сетка n x n единичных кубов с шагом 2 в плоскости xz: (прокси, бокс)
+    fn grid(bvh: &mut DynamicBvh, n: i32, at: Vec3) -> Vec<(i32, Aabb)> {
+        let mut proxies = Vec::new();
+        for i in 0..n * n {
+            let c = at + Vec3::new((i % n) as f32 * 2.0, 0.0, (i / n) as f32 * 2.0);
+            let bbox = Aabb::new(c - Vec3::splat(0.5), c + Vec3::splat(0.5));
+            proxies.push((bvh.create_proxy(i, &bbox, bvh.margin), bbox));
+        }
+        proxies
+    }
+
+    fn shifted(proxies: &[(i32, Aabb)], d: impl Fn(usize) -> Vec3) -> Vec<(i32, Aabb, Vec3)> {
+        proxies.iter().enumerate()
+            .map(|(i, &(proxy, b))| (proxy, Aabb::new(b.min + d(i), b.max + d(i)), d(i)))
+            .collect()
+    }
+
+    fn assert_covers(bvh: &DynamicBvh, moved: &[(i32, Aabb, Vec3)]) {
+        assert_eq!(validate_bvh(bvh).unwrap(), bvh.proxy_count as usize);
+        for (proxy, bbox, _) in moved {
+            assert!(bvh.nodes[*proxy as usize].bbox.contains(*bbox), "leaf {} lost its object", proxy);
+        }
+    }
+
+    #[test]
+    fn move_proxy_keeps_fitting_leaves() {
+        let mut bvh = DynamicBvh::new();
+        let proxies = grid(&mut bvh, 3, Vec3::ZERO);
+        let (proxy, bbox) = proxies[4];
+        let step = Vec3::new(0.1, 0.0, 0.0);
+
+        // Внутри margin лист не трогается
+        assert!(!bvh.move_proxy(proxy, &Aabb::new(bbox.min + step, bbox.max + step), step));
+        assert_eq!((bvh.stats.moves, bvh.stats.reinserts, bvh.stats.avoided()), (1, 0, 1));
+
+        // Вышел за лист — перевставка с запасом, вытянутым по движению; индекс прокси прежний
+        let d = Vec3::new(0.5, 0.0, 0.0);
+        let moved = Aabb::new(bbox.min + d, bbox.max + d);
+        assert!(bvh.move_proxy(proxy, &moved, d));
+        let leaf = bvh.nodes[proxy as usize].bbox;
+        let r = bvh.margin;
+        assert_eq!(leaf.min, moved.min - Vec3::splat(r));
+        assert_eq!(leaf.max, moved.max + Vec3::splat(r) + d * bvh.velocity_multiplier);
+        assert_eq!(bvh.nodes[proxy as usize].object_index, 4);
+
+        // При равномерном движении следующий кадр укладывается в вытянутый лист
+        let next = Aabb::new(moved.min + d, moved.max + d);
+        assert!(!bvh.move_proxy(proxy, &next, d));
+        assert_eq!(bvh.stats.reinserts, 1);
+        assert!((bvh.stats.avoided_ratio() - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(validate_bvh(&bvh).unwrap(), 9);
+    }
+
+    #[test]
+    fn per_proxy_margin() {
+        let mut bvh = DynamicBvh::new();
+        let proxies = grid(&mut bvh, 2, Vec3::ZERO);
+        let (tight, bbox) = proxies[0];
+        bvh.set_margin(tight, 0.0);
+        let d = Vec3::new(0.05, 0.0, 0.0);
+        // Без запаса любой сдвиг выводит из листа, а лист вытягивается только по движению
+        assert!(bvh.move_proxy(tight, &Aabb::new(bbox.min + d, bbox.max + d), d));
+        assert_eq!(bvh.nodes[tight as usize].bbox.min, bbox.min + d);
+        // У соседа запас по умолчанию
+        let (other, bbox) = proxies[1];
+        assert!(!bvh.move_proxy(other, &Aabb::new(bbox.min + d, bbox.max + d), d));
+
+        // Лист, раздутый быстрым движением, ужимается, когда объект остановился
+        let big = Vec3::new(5.0, 0.0, 0.0);
+        let far = Aabb::new(bbox.min + big, bbox.max + big);
+        assert!(bvh.move_proxy(other, &far, big));
+        assert!(bvh.move_proxy(other, &far, Vec3::ZERO));
+        let leaf = bvh.nodes[other as usize].bbox;
+        assert_eq!((leaf.min, leaf.max), (far.min - Vec3::splat(bvh.margin), far.max + Vec3::splat(bvh.margin)));
+        assert_eq!(validate_bvh(&bvh).unwrap(), 4);
+    }
+
+    #[test]
+    fn few_moves_are_reinserted() {
+        let mut bvh = DynamicBvh::new();
+        let proxies = grid(&mut bvh, 10, Vec3::ZERO);
+        let moved = shifted(&proxies[..5], |_| Vec3::new(3.0, 0.0, 0.0));
+        assert_eq!(bvh.update_proxies(&moved), UpdateStrategy::Reinsert);
+        assert_eq!((bvh.stats.reinserts, bvh.stats.refits, bvh.stats.rebuilds), (5, 0, 0));
+        assert_covers(&bvh, &moved);
+    }
+
+    #[test]
+    fn small_mass_moves_are_refitted() {
+        let mut bvh = DynamicBvh::new();
+        let proxies = grid(&mut bvh, 10, Vec3::ZERO);
+        bvh.rebuild();
+        let moved = shifted(&proxies, |i| Vec3::new(if i % 2 == 0 { 0.5 } else { -0.5 }, 0.0, 0.0));
+        assert_eq!(bvh.update_proxies(&moved), UpdateStrategy::Refit);
+        assert_eq!((bvh.stats.reinserts, bvh.stats.rebuilds), (0, 1));
+        assert_covers(&bvh, &moved);
+    }
+
+    #[test]
+    fn jitter_inside_fat_boxes_leaves_the_tree_alone() {
+        let mut bvh = DynamicBvh::new();
+        let proxies = grid(&mut bvh, 10, Vec3::ZERO);
+        let moved = shifted(&proxies, |i| Vec3::new(if i % 2 == 0 { 0.1 } else { -0.1 }, 0.0, 0.0));
+        assert_eq!(bvh.update_proxies(&moved), UpdateStrategy::Unchanged);
+        assert_eq!((bvh.stats.moves, bvh.stats.reinserts, bvh.stats.refits, bvh.stats.rebuilds), (100, 0, 0, 0));
+        assert_eq!(bvh.update_proxies(&[]), UpdateStrategy::Unchanged);
+        assert_covers(&bvh, &moved);
+    }
+
+    #[test]
+    fn scrambling_moves_trigger_a_rebuild() {
+        let mut bvh = DynamicBvh::new();
+        let proxies = grid(&mut bvh, 10, Vec3::ZERO);
+        bvh.rebuild();
+        // Соседние по дереву листья разлетаются в разные углы — refit даёт огромные узлы
+        let moved = shifted(&proxies, |i| Vec3::new(((i * 37) % 100) as f32 - 50.0, 0.0, ((i * 61) % 100) as f32 - 50.0));
+        assert_eq!(bvh.update_proxies(&moved), UpdateStrategy::Rebuild);
+        assert_eq!(bvh.stats.rebuilds, 2);
+        assert_eq!(bvh.sah_reference, bvh.sah_cost());
+        assert_covers(&bvh, &moved);
+    }
+
+    #[test]
+    fn inserts_and_removals_reset_the_sah_reference() {
+        let mut bvh = DynamicBvh::new();
+        let small = grid(&mut bvh, 2, Vec3::ZERO);
+        bvh.rebuild();
+        assert!(bvh.sah_reference > 0.0);
+
+        // Дерево выросло вставками: эталон маленького дерева к нему не применим, refit не должен
+        // из-за него считаться испорченным
+        let mut all = small.clone();
+        all.extend(grid(&mut bvh, 10, Vec3::new(10.0, 0.0, 0.0)));
+        assert_eq!(bvh.sah_reference, 0.0);
+        let moved = shifted(&all, |_| Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!(bvh.update_proxies(&moved), UpdateStrategy::Refit);
+        assert!(bvh.sah_reference > 0.0);
+        assert_covers(&bvh, &moved);
+
+        bvh.remove_leaf(all[0].0);
+        assert_eq!(bvh.sah_reference, 0.0);
+        assert_eq!(validate_bvh(&bvh).unwrap(), all.len() - 1);
+    }
+}
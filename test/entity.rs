@@ -1,40 +1,180 @@
 use crate::Aabb;
 use crate::Vec3;
+use crate::geometry::Obb;
+use crate::ray::Ray;
+use glam::{DVec3, Quat};
 pub struct EntityData {
     pub health: f32,
     pub is_dirty: bool,
 }
+// Компонент (см. components.rs): сущность живёт remaining секунд, потом World::step зовёт её on_expire,
+// выпускает Event::Expired и удаляет её
+pub struct Lifetime {
+    pub remaining: f32,
+}
+impl Lifetime {
+    pub fn new(seconds: f32) -> Self {
+        Self { remaining: seconds }
+    }
+}
+// Компонент зоны урона (ядовитое облако, лава): каждый step наносит per_second * dt всем,
+// кого зона видит по своим слоям (World::filter_for)
+pub struct DamageZone {
+    pub per_second: f32,
+}
+// Компонент твёрдого тела (ящики, обломки, снаряды): World::step_physics двигает его со скоростью velocity,
+// тянет гравитацией и выталкивает из пересечений. Симулируются только Dynamic без родителя; остальные
+// тела и сущности без компонента для него — неподвижная стена бесконечной массы.
+pub struct RigidBody {
+    pub velocity: Vec3,
+    pub inv_mass: f32,    // 1 / масса, 0 — бесконечная масса (гравитация и удары не действуют)
+    pub restitution: f32, // упругость: 0 — не отскакивает, 1 — без потерь
+    pub friction: f32,
+}
+impl RigidBody {
+    pub fn new(mass: f32) -> Self {
+        Self { velocity: Vec3::ZERO, inv_mass: if mass > 0.0 { 1.0 / mass } else { 0.0 }, restitution: 0.0, friction: 0.5 }
+    }
+    pub fn mass(&self) -> f32 {
+        if self.inv_mass > 0.0 { 1.0 / self.inv_mass } else { 0.0 }
+    }
+}
+// Как сущность двигается. Static живёт в отдельном дереве World::static_bvh, собранном по SAH, и
+// перестановки подвижных его не трогают. Dynamic и Kinematic — в World::bvh; Kinematic двигает только
+// код (двери, лифты), в остальном они одинаковы.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyKind {
+    Static,
+    Dynamic,
+    Kinematic,
+}
+impl BodyKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BodyKind::Static => "static",
+            BodyKind::Dynamic => "dynamic",
+            BodyKind::Kinematic => "kinematic",
+        }
+    }
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "static" => Some(BodyKind::Static),
+            "dynamic" => Some(BodyKind::Dynamic),
+            "kinematic" => Some(BodyKind::Kinematic),
+            _ => None,
+        }
+    }
+}
+// Активность: спящих не проверяют триггеры (пока их не разбудят), отключённых не видят запросы
+// по умолчанию, см. QueryFilter и activity.rs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activity {
+    Awake,
+    Sleeping,
+    Disabled,
+}
+impl Activity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Activity::Awake => "awake",
+            Activity::Sleeping => "sleeping",
+            Activity::Disabled => "disabled",
+        }
+    }
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "awake" => Some(Activity::Awake),
+            "sleeping" => Some(Activity::Sleeping),
+            "disabled" => Some(Activity::Disabled),
+            _ => None,
+        }
+    }
+}
 #[repr(C)]
 pub struct Entity {
     pub id: i32,
     pub pos: Vec3,
+    pub world_pos: Option<DVec3>, // точная позиция в мире для больших уровней; pos = world_pos - World::origin
     pub size: Vec3,
+    pub rotation: Option<Quat>, // None — бокс выровнен по осям; повёрнутый проверяется как OBB, см. geometry.rs
     pub category: i32,
     pub mask: i32,
+    pub proxy: i32, // лист BVH (индекс прокси), -1 — ещё не в дереве
+    pub body: BodyKind, // в каком дереве лист: World::static_bvh или World::bvh
+    pub activity: Activity,
+    pub idle_steps: u32, // сколько step подряд не двигалась; после World::sleep_after засыпает
+    pub parent: i32, // -1 — корень; pos и rotation ребёнка выводятся из родителя, см. hierarchy.rs
+    pub children: Vec<i32>,
+    pub local_pos: Vec3, // смещение и поворот относительно родителя, только при parent != -1
+    pub local_rotation: Option<Quat>,
     pub gameplay: EntityData,
-    pub on_trigger: Option<Box<dyn FnMut(i32)>>,
-    pub on_interact: Option<Box<dyn Fn()>>,
+    pub on_trigger: Option<String>,  // имя обработчика в World::events для Event::Triggered
+    pub on_interact: Option<String>, // то же для Event::Interacted
+    pub on_expire: Option<String>,   // то же для Event::Expired, зовётся из World::step, пока сущность ещё жива
 }
 impl Entity {
     pub fn new(id: i32, pos: Vec3, size: Vec3, cat: i32, mask: i32) -> Self {
         Self {
-            id: id,
-            pos: pos,
-            size: size,
+            id,
+            pos,
+            world_pos: None,
+            size,
+            rotation: None,
             category: cat,
-            mask: mask,
+            mask,
+            proxy: -1,
+            body: BodyKind::Dynamic,
+            activity: Activity::Awake,
+            idle_steps: 0,
+            parent: -1,
+            children: Vec::new(),
+            local_pos: Vec3::ZERO,
+            local_rotation: None,
             gameplay: EntityData {
                 health: 100.0,
                 is_dirty: false,
             },
             on_interact: None,
             on_trigger: None,
+            on_expire: None,
         }
     }
+    // Для дерева: точный AABB, у повёрнутой сущности — плотный AABB её OBB
     pub fn get_aabb(&self) -> Aabb {
-        return Aabb {
+        if self.rotation.is_some() {
+            return self.get_obb().aabb();
+        }
+        Aabb {
             min: self.pos - (self.size * 0.5),
             max: self.pos + (self.size * 0.5),
-        };
+        }
+    }
+    pub fn get_obb(&self) -> Obb {
+        Obb::new(self.pos, self.size * 0.5, self.rotation.unwrap_or(Quat::IDENTITY))
+    }
+    // Узкая фаза: без поворота хватает AABB, иначе проверяется настоящий OBB
+    pub fn overlaps_aabb(&self, bbox: &Aabb) -> bool {
+        match self.rotation {
+            None => self.get_aabb().intersects(bbox),
+            Some(_) => self.get_obb().intersects_aabb(bbox),
+        }
+    }
+    pub fn overlaps(&self, other: &Entity) -> bool {
+        match (self.rotation, other.rotation) {
+            (None, None) => self.get_aabb().intersects(&other.get_aabb()),
+            _ => self.get_obb().intersects(&other.get_obb()),
+        }
+    }
+    pub fn ray_hit(&self, ray: &Ray) -> Option<f32> {
+        match self.rotation {
+            None => self.get_aabb().ray_hit(ray),
+            Some(_) => self.get_obb().ray_hit(ray),
+        }
+    }
+    pub fn distance_sq(&self, p: Vec3) -> f32 {
+        match self.rotation {
+            None => self.get_aabb().distance_sq(p),
+            Some(_) => self.get_obb().distance_sq(p),
+        }
     }
 }
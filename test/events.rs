@@ -0,0 +1,183 @@
+// Очередь событий мира и именованные обработчики.
+// World складывает события в очередь по ходу работы; системы забирают их после step через
+// World::dispatch_events (с вызовом обработчиков) или World::drain_events (только сами события).
+// Сущности ссылаются на обработчики по имени (Entity::on_trigger / on_interact / on_expire), поэтому имена
+// сохраняются вместе с миром, а после загрузки достаточно заново зарегистрировать те же обработчики.
+// Очередь ограничена: если события никто не забирает, самые старые вытесняются новыми.
+use crate::Vec3;
+use crate::world::World;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Spawned { id: i32 },
+    Destroyed { id: i32 },
+    Moved { id: i32, from: Vec3, to: Vec3 },
+    Triggered { trigger: i32, other: i32 }, // other вошёл в объём trigger
+    Interacted { target: i32, actor: i32 },
+    Expired { id: i32 }, // кончился Lifetime; Destroyed придёт следом
+    Died { id: i32 },    // здоровье упало до нуля от урона; Destroyed — после cleanup
+}
+
+pub type Handler = Box<dyn FnMut(&mut World, &Event)>;
+
+// Сколько раз подряд доставлять события, порождённые обработчиками, прежде чем отложить их до следующего вызова
+const MAX_DISPATCH_ROUNDS: usize = 16;
+
+pub struct EventBus {
+    queue: VecDeque<Event>,
+    handlers: HashMap<String, Handler>,
+    listeners: Vec<String>, // обработчики, получающие все события
+    pub capacity: usize,    // = 65536; больше событий очередь не держит
+    pub dropped: u64,       // сколько старых событий вытеснено переполнением
+}
+
+#[rustfmt::skip]
+impl EventBus {
+    pub fn new() -> Self {
+        Self { queue: VecDeque::new(), handlers: HashMap::new(), listeners: Vec::new(), capacity: 1 << 16, dropped: 0 }
+    }
+    pub fn emit(&mut self, event: Event) {
+        while self.queue.len() >= self.capacity.max(1) {
+            self.queue.pop_front();
+            self.dropped += 1;
+        }
+        self.queue.push_back(event);
+    }
+    pub fn pending(&self) -> &VecDeque<Event> {
+        &self.queue
+    }
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+    // Повторная регистрация под тем же именем заменяет обработчик
+    pub fn register(&mut self, name: &str, handler: impl FnMut(&mut World, &Event) + 'static) {
+        self.handlers.insert(name.to_string(), Box::new(handler));
+    }
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.handlers.contains_key(name)
+    }
+    pub fn listen(&mut self, name: &str) {
+        if !self.listeners.iter().any(|n| n == name) { self.listeners.push(name.to_string()); }
+    }
+}
+
+impl World {
+    pub fn drain_events(&mut self) -> Vec<Event> {
+        self.events.queue.drain(..).collect()
+    }
+    // Доставляет очередь обработчикам: слушателям — всё, сущностям — их Triggered/Interacted.
+    // События, порождённые обработчиками, доставляются в этом же вызове. Возвращает все доставленные.
+    pub fn dispatch_events(&mut self) -> Vec<Event> {
+        let mut delivered = Vec::new();
+        for _ in 0..MAX_DISPATCH_ROUNDS {
+            let batch = self.drain_events();
+            if batch.is_empty() { break; }
+            for event in &batch { self.deliver(event); }
+            delivered.extend(batch);
+        }
+        delivered
+    }
+    // Одно событие сразу, мимо очереди: то, что уже ждёт в ней, остаётся до dispatch_events
+    pub fn deliver(&mut self, event: &Event) {
+        let mut names = self.events.listeners.clone();
+        let hook = match *event {
+            Event::Triggered { trigger, .. } => self.registry.get(trigger).and_then(|e| e.on_trigger.clone()),
+            Event::Interacted { target, .. } => self.registry.get(target).and_then(|e| e.on_interact.clone()),
+            _ => None,
+        };
+        names.extend(hook);
+        for name in names { self.call_handler(&name, event); }
+    }
+    // Обработчик на время вызова вынимается из карты, чтобы ему можно было отдать &mut World
+    pub(crate) fn call_handler(&mut self, name: &str, event: &Event) {
+        let Some(mut handler) = self.events.handlers.remove(name) else { return };
+        handler(self, event);
+        self.events.handlers.entry(name.to_string()).or_insert(handler);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, MAX_DISPATCH_ROUNDS};
+    use crate::Vec3;
+    use crate::world::World;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Обработчик, записывающий всё, что ему доставили
+    fn recorder(world: &mut World, name: &str) -> Rc<RefCell<Vec<Event>>> {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        world.events.register(name, move |_, event| log.borrow_mut().push(event.clone()));
+        seen
+    }
+
+    #[test]
+    fn dispatch_reaches_listeners_and_entity_handlers() {
+        let mut world = World::new();
+        let all = recorder(&mut world, "all");
+        let door = recorder(&mut world, "door");
+        world.events.listen("all");
+        world.events.listen("all");
+        let (a, b) = (world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1), world.create_entity(Vec3::X, Vec3::ONE, 1, 1));
+        world.registry[b].on_interact = Some("door".to_string());
+        world.registry[a].on_interact = Some("missing".to_string());
+
+        world.interact(a, b);
+        world.interact(b, a);
+        let delivered = world.dispatch_events();
+        assert_eq!(delivered.len(), 4);
+        assert_eq!(*all.borrow(), delivered, "a listener is called once per event, however often it listens");
+        assert_eq!(*door.borrow(), [Event::Interacted { target: b, actor: a }]);
+        assert!(world.events.pending().is_empty() && world.dispatch_events().is_empty());
+    }
+
+    #[test]
+    fn handlers_can_emit_and_replace() {
+        let mut world = World::new();
+        let id = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1);
+        world.drain_events();
+        // Каждое Interacted порождает следующее — цепочка обрывается после MAX_DISPATCH_ROUNDS кругов
+        world.events.register("echo", |world, event| {
+            if let Event::Interacted { target, actor } = *event { world.interact(actor, target); }
+        });
+        world.registry[id].on_interact = Some("echo".to_string());
+        world.interact(id, id);
+        assert_eq!(world.dispatch_events().len(), MAX_DISPATCH_ROUNDS);
+        assert_eq!(world.events.pending().len(), 1, "the rest waits for the next dispatch");
+
+        // Та же регистрация заменяет обработчик, в том числе изнутри самого обработчика
+        let seen = recorder(&mut world, "echo");
+        assert!(world.events.is_registered("echo") && !world.events.is_registered("nope"));
+        world.dispatch_events();
+        assert_eq!(seen.borrow().len(), 1);
+        world.events.register("echo", |world, _| world.events.register("echo", |_, _| {}));
+        world.interact(id, id);
+        world.dispatch_events();
+        assert!(world.events.is_registered("echo"));
+        assert_eq!(seen.borrow().len(), 1);
+    }
+
+    #[test]
+    fn full_queue_drops_the_oldest() {
+        let mut world = World::new();
+        world.events.capacity = 3;
+        let ids: Vec<i32> = (0..5).map(|_| world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1)).collect();
+        assert_eq!(world.events.dropped, 2);
+        assert_eq!(world.drain_events(), ids[2..].iter().map(|&id| Event::Spawned { id }).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn standing_still_is_not_a_move() {
+        let mut world = World::new();
+        let id = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1);
+        let other = world.create_entity(Vec3::X, Vec3::ONE, 1, 1);
+        world.step(0.1);
+        world.drain_events();
+        world.update_position(id, Vec3::ZERO);
+        world.update_positions(&[(id, Vec3::ZERO), (other, Vec3::new(2.0, 0.0, 0.0))]);
+        assert_eq!(world.drain_events(), [Event::Moved { id: other, from: Vec3::X, to: Vec3::new(2.0, 0.0, 0.0) }]);
+        assert!(!world.recently_moved.contains(&id));
+    }
+}
@@ -0,0 +1,119 @@
+// Фильтр запросов к миру: слои, исключаемые слои, список игнорируемых id, активность и произвольный предикат.
+// Проверяется на листе дерева, до точного теста AABB, так что отброшенные сущности ничего не стоят.
+use crate::entity::{Activity, Entity};
+use crate::layers::{CollisionLayers, LayerFilter};
+
+pub type Predicate<'a> = Box<dyn Fn(&Entity) -> bool + 'a>;
+
+pub struct QueryFilter<'a> {
+    pub layers: LayerFilter,  // include: кто спрашивает и какие слои ищет
+    pub exclude: i32,         // слои, которые отбрасываются всегда
+    pub ignore: Vec<i32>,     // id, которые отбрасываются (например, сам стрелок)
+    pub predicate: Option<Predicate<'a>>,
+    pub sleeping: bool,       // спящие участвуют (по умолчанию да — они никуда не делись)
+    pub disabled: bool,       // отключённые участвуют (по умолчанию нет)
+}
+
+impl Default for QueryFilter<'_> {
+    fn default() -> Self {
+        Self { layers: LayerFilter::ANY, exclude: 0, ignore: Vec::new(), predicate: None, sleeping: true, disabled: false }
+    }
+}
+
+impl From<LayerFilter> for QueryFilter<'_> {
+    fn from(layers: LayerFilter) -> Self {
+        Self { layers, ..Default::default() }
+    }
+}
+
+impl<'a> QueryFilter<'a> {
+    // Без фильтрации — всё, что пересекается
+    pub fn any() -> Self {
+        Self::default()
+    }
+    pub fn layers(mut self, category: i32, mask: i32) -> Self {
+        self.layers = LayerFilter::new(category, mask);
+        self
+    }
+    pub fn exclude(mut self, mask: i32) -> Self {
+        self.exclude |= mask;
+        self
+    }
+    pub fn ignore(mut self, id: i32) -> Self {
+        self.ignore.push(id);
+        self
+    }
+    // Несколько предикатов объединяются через "и"
+    pub fn with(mut self, predicate: impl Fn(&Entity) -> bool + 'a) -> Self {
+        self.predicate = Some(match self.predicate.take() {
+            Some(prev) => Box::new(move |e| prev(e) && predicate(e)),
+            None => Box::new(predicate),
+        });
+        self
+    }
+    // Мёртвые (health <= 0) не участвуют
+    pub fn alive(self) -> Self {
+        self.with(|e| e.gameplay.health > 0.0)
+    }
+    // Помеченные на удаление не участвуют
+    pub fn skip_dirty(self) -> Self {
+        self.with(|e| !e.gameplay.is_dirty)
+    }
+    pub fn sleeping(mut self, include: bool) -> Self {
+        self.sleeping = include;
+        self
+    }
+    pub fn disabled(mut self, include: bool) -> Self {
+        self.disabled = include;
+        self
+    }
+    pub fn accepts(&self, layers: &CollisionLayers, e: &Entity) -> bool {
+        let active = match e.activity {
+            Activity::Awake => true,
+            Activity::Sleeping => self.sleeping,
+            Activity::Disabled => self.disabled,
+        };
+        active
+            && e.category & self.exclude == 0
+            && self.layers.accepts(layers, e.category)
+            && !self.ignore.contains(&e.id)
+            && self.predicate.as_ref().is_none_or(|p| p(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryFilter;
+    use crate::Vec3;
+    use crate::entity::{Activity, Entity};
+    use crate::layers::CollisionLayers;
+
+    #[test]
+    fn each_rule_rejects_on_its_own() {
+        let layers = CollisionLayers::with_defaults();
+        let (stat, player) = (layers.bit("static").unwrap(), layers.bit("player").unwrap());
+        let entity = |id: i32, category: i32| Entity::new(id, Vec3::ZERO, Vec3::ONE, category, 0);
+        let (wall, hero) = (entity(1, stat), entity(2, player));
+        let all = QueryFilter::any();
+        assert!(all.accepts(&layers, &wall) && all.accepts(&layers, &hero));
+
+        assert!(!QueryFilter::any().exclude(stat).accepts(&layers, &wall));
+        assert!(!QueryFilter::any().ignore(2).accepts(&layers, &hero));
+        assert!(QueryFilter::any().ignore(2).accepts(&layers, &wall));
+        assert!(!QueryFilter::any().layers(player, stat).accepts(&layers, &hero));
+        assert!(!QueryFilter::any().with(|e| e.id != 1).with(|e| e.id != 2).accepts(&layers, &hero));
+
+        let mut dead = entity(3, player);
+        dead.gameplay.health = 0.0;
+        assert!(!QueryFilter::any().alive().accepts(&layers, &dead));
+        dead.gameplay.is_dirty = true;
+        assert!(!QueryFilter::any().skip_dirty().accepts(&layers, &dead));
+
+        // Спящие по умолчанию видны, отключённые — нет
+        let mut idle = entity(4, player);
+        idle.activity = Activity::Sleeping;
+        assert!(all.accepts(&layers, &idle) && !QueryFilter::any().sleeping(false).accepts(&layers, &idle));
+        idle.activity = Activity::Disabled;
+        assert!(!all.accepts(&layers, &idle) && QueryFilter::any().disabled(true).accepts(&layers, &idle));
+    }
+}
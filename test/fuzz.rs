@@ -0,0 +1,218 @@
+// Интерпретатор произвольных байтов как последовательности операций над DynamicBvh — точка входа для фаззинга.
+// После каждой операции проверяются инварианты дерева (validate_bvh) и результаты запросов против перебора.
+//
+// Формат: поток команд, первый байт — код операции (по модулю 7), дальше аргументы.
+// Координата — i16 LE / 16, размер — (u8 + 1) / 16, слот — u8 по модулю числа живых прокси.
+//   0 insert  pos[3], size[3]         (чётные коды — insert_leaf с точным AABB, нечётные — create_proxy с запасом)
+//   1 remove  slot
+//   2 update  slot, pos[3]           (move_proxy со смещением от прошлой позиции)
+//   3 query   min[3], size[3]
+//   4 ray     origin[3], dir: 3 x i8 (+0.5, чтобы не было нулевых компонент)
+//   5 batch   n (1..=16), n x (slot, pos[3])  (update_proxies: перевставка / refit / пересборка)
+//   6 rebuild
+// Если аргументов не хватает — разбор заканчивается.
+use crate::Aabb;
+use crate::DynamicBvh;
+use crate::Vec3;
+use crate::ray::Ray;
+use crate::bvhcheck::validate_bvh;
+use std::collections::HashSet;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Option<u8> {
+        let b = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+    fn coord(&mut self) -> Option<f32> {
+        let lo = self.u8()?;
+        let hi = self.u8()?;
+        Some(i16::from_le_bytes([lo, hi]) as f32 / 16.0)
+    }
+    fn point(&mut self) -> Option<Vec3> {
+        Some(Vec3::new(self.coord()?, self.coord()?, self.coord()?))
+    }
+    fn size(&mut self) -> Option<Vec3> {
+        let mut s = [0.0; 3];
+        for v in &mut s { *v = (self.u8()? as f32 + 1.0) / 16.0; }
+        Some(Vec3::from_array(s))
+    }
+    fn dir(&mut self) -> Option<Vec3> {
+        let mut d = [0.0; 3];
+        for v in &mut d { *v = self.u8()? as i8 as f32 + 0.5; }
+        Some(Vec3::from_array(d))
+    }
+}
+
+struct Proxy {
+    object: i32,
+    leaf: i32,
+    bbox: Aabb,
+}
+
+fn check(bvh: &DynamicBvh, proxies: &[Proxy], step: usize) {
+    let leaves = validate_bvh(bvh).unwrap_or_else(|e| panic!("step {}: {}", step, e));
+    assert_eq!(leaves, proxies.len(), "step {}: leaf count", step);
+    for p in proxies {
+        let node = &bvh.nodes[p.leaf as usize];
+        assert!(node.is_leaf && node.object_index == p.object, "step {}: proxy {} lost its leaf", step, p.object);
+        assert!(node.bbox.contains(p.bbox), "step {}: leaf of {} does not contain its box", step, p.object);
+    }
+}
+
+fn check_hits(got: &[i32], exact: HashSet<i32>, proxies: &[Proxy], step: usize) {
+    let got_set: HashSet<i32> = got.iter().copied().collect();
+    assert_eq!(got_set.len(), got.len(), "step {}: duplicate hits {:?}", step, got);
+    for id in &got_set {
+        assert!(proxies.iter().any(|p| p.object == *id), "step {}: hit removed object {}", step, id);
+    }
+    assert!(exact.is_subset(&got_set), "step {}: missing hits {:?}", step, exact.difference(&got_set).collect::<Vec<_>>());
+}
+
+pub fn run_bytes(data: &[u8]) {
+    let mut bvh = DynamicBvh::new();
+    let mut proxies: Vec<Proxy> = Vec::new();
+    let mut next_object = 0;
+    let mut r = Reader { data, pos: 0 };
+    let mut step = 0;
+
+    while let Some(op) = r.u8() {
+        match op % 7 {
+            0 => {
+                let (Some(pos), Some(size)) = (r.point(), r.size()) else { break };
+                let bbox = Aabb::new(pos - size * 0.5, pos + size * 0.5);
+                next_object += 1;
+                let leaf = if op % 2 == 0 {
+                    bvh.insert_leaf(next_object, &bbox)
+                } else {
+                    bvh.create_proxy(next_object, &bbox, bvh.margin)
+                };
+                proxies.push(Proxy { object: next_object, leaf, bbox });
+            }
+            1 => {
+                let Some(slot) = r.u8() else { break };
+                if proxies.is_empty() { continue; }
+                let p = proxies.swap_remove(slot as usize % proxies.len());
+                bvh.remove_leaf(p.leaf);
+            }
+            2 => {
+                let (Some(slot), Some(pos)) = (r.u8(), r.point()) else { break };
+                if proxies.is_empty() { continue; }
+                let i = slot as usize % proxies.len();
+                let p = &mut proxies[i];
+                let half = (p.bbox.max - p.bbox.min) * 0.5;
+                let displacement = pos - (p.bbox.min + half);
+                p.bbox = Aabb::new(pos - half, pos + half);
+                bvh.move_proxy(p.leaf, &p.bbox, displacement);
+            }
+            3 => {
+                let (Some(min), Some(size)) = (r.point(), r.size()) else { break };
+                let bbox = Aabb::new(min, min + size);
+                let mut got = Vec::new();
+                bvh.query_filtered(&bbox, |_| true, &mut got);
+                let exact = proxies.iter()
+                    .filter(|p| p.bbox.min.cmple(bbox.max).all() && p.bbox.max.cmpge(bbox.min).all())
+                    .map(|p| p.object)
+                    .collect();
+                check_hits(&got, exact, &proxies, step);
+            }
+            5 => {
+                let Some(n) = r.u8() else { break };
+                let mut moved = Vec::new();
+                for _ in 0..n % 16 + 1 {
+                    let (Some(slot), Some(pos)) = (r.u8(), r.point()) else { break };
+                    if proxies.is_empty() { continue; }
+                    let i = slot as usize % proxies.len();
+                    let p = &mut proxies[i];
+                    let half = (p.bbox.max - p.bbox.min) * 0.5;
+                    let displacement = pos - (p.bbox.min + half);
+                    p.bbox = Aabb::new(pos - half, pos + half);
+                    moved.push((p.leaf, p.bbox, displacement));
+                }
+                bvh.update_proxies(&moved);
+            }
+            6 => bvh.rebuild(),
+            _ => {
+                let (Some(origin), Some(dir)) = (r.point(), r.dir()) else { break };
+                let ray = Ray::new(origin, dir);
+                let got = bvh.ray_cast(&ray);
+                let exact = proxies.iter()
+                    .filter(|p| p.bbox.intersect_ray(&ray))
+                    .map(|p| p.object)
+                    .collect();
+                check_hits(&got, exact, &proxies, step);
+            }
+        }
+        check(&bvh, &proxies, step);
+        step += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_bytes;
+    use crate::rng::Rng;
+    use std::path::PathBuf;
+
+    fn corpus() -> Vec<(PathBuf, Vec<u8>)> {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/bvh_ops");
+        let mut files: Vec<(PathBuf, Vec<u8>)> = std::fs::read_dir(&dir)
+            .unwrap_or_else(|e| panic!("{}: {}", dir.display(), e))
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let bytes = std::fs::read(&path).unwrap();
+                (path, bytes)
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn corpus_replays_cleanly() {
+        let files = corpus();
+        assert!(!files.is_empty());
+        for (path, bytes) in files {
+            println!("{}", path.display());
+            run_bytes(&bytes);
+        }
+    }
+
+    // Локальный фаззер без внешних инструментов: мутации корпуса (замена/вставка/удаление/склейка байтов).
+    // Число итераций — BVH_FUZZ_ITERS, seed — BVH_SEED; упавший вход печатается в hex.
+    #[test]
+    fn mutated_corpus() {
+        let env = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        let iters = env("BVH_FUZZ_ITERS").unwrap_or(500);
+        let mut rng = Rng::new(env("BVH_SEED").unwrap_or(0));
+        let files = corpus();
+
+        for _ in 0..iters {
+            let mut input = files[rng.below(files.len() as u64) as usize].1.clone();
+            for _ in 0..1 + rng.below(8) {
+                let at = rng.below(input.len() as u64 + 1) as usize;
+                match rng.below(4) {
+                    0 if at < input.len() => input[at] = rng.next_u64() as u8,
+                    1 => input.insert(at, rng.next_u64() as u8),
+                    2 if at < input.len() => { input.remove(at); }
+                    _ => {
+                        let other = &files[rng.below(files.len() as u64) as usize].1;
+                        let from = rng.below(other.len() as u64) as usize;
+                        let len = usize::min(rng.below(32) as usize, other.len() - from);
+                        input.splice(at..at, other[from..from + len].iter().copied());
+                    }
+                }
+            }
+            let result = std::panic::catch_unwind(|| run_bytes(&input));
+            if result.is_err() {
+                let hex: String = input.iter().map(|b| format!("{:02x}", b)).collect();
+                panic!("fuzz input failed: {}", hex);
+            }
+        }
+    }
+}
@@ -0,0 +1,29 @@
+#![no_main]
+// Запуск: cargo fuzz run bvh_ops (из каталога test/), корпус — fuzz/corpus/bvh_ops.
+// Основной крейт бинарный, поэтому нужные модули подключаются напрямую по пути — только дерево
+// и его проверка, без World. Часть API дерева фаззер не вызывает, отсюда allow(dead_code) на этих модулях.
+use aabb::Aabb;
+use dynbvh::DynamicBvh;
+use glam::Vec3;
+use stack::Stack;
+#[path = "../../aabb.rs"]
+#[allow(dead_code)]
+mod aabb;
+#[path = "../../bvhcheck.rs"]
+mod bvhcheck;
+#[path = "../../dynbvh.rs"]
+#[allow(dead_code)]
+mod dynbvh;
+#[path = "../../fuzz.rs"]
+mod fuzz;
+#[path = "../../node.rs"]
+mod node;
+#[path = "../../ray.rs"]
+#[allow(dead_code)]
+mod ray;
+#[path = "../../stack.rs"]
+mod stack;
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+    fuzz::run_bytes(data);
+});
@@ -0,0 +1,272 @@
+// Геометрия узкой фазы: ориентированный бокс (OBB) для повёрнутых сущностей и контакты пар.
+// Дерево по-прежнему хранит AABB — Obb::aabb даёт плотный охватывающий бокс, а запросы, лучи и
+// перекрытия проверяют уже сам OBB.
+use crate::Aabb;
+use crate::Vec3;
+use crate::ray::Ray;
+use glam::{Mat3, Quat};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Obb {
+    pub center: Vec3,
+    pub half: Vec3, // половины размеров вдоль собственных осей
+    pub rotation: Quat,
+}
+
+#[rustfmt::skip]
+impl Obb {
+    pub fn new(center: Vec3, half: Vec3, rotation: Quat) -> Self {
+        Self { center, half, rotation }
+    }
+    pub fn from_aabb(b: &Aabb) -> Self {
+        Self::new((b.min + b.max) * 0.5, (b.max - b.min) * 0.5, Quat::IDENTITY)
+    }
+    // Собственные оси бокса в мировых координатах
+    pub fn axes(&self) -> [Vec3; 3] {
+        let m = Mat3::from_quat(self.rotation);
+        [m.x_axis, m.y_axis, m.z_axis]
+    }
+    // Плотный AABB: проекция каждой оси с её половиной размера
+    pub fn aabb(&self) -> Aabb {
+        let [x, y, z] = self.axes();
+        let extent = x.abs() * self.half.x + y.abs() * self.half.y + z.abs() * self.half.z;
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+    pub fn corners(&self) -> [Vec3; 8] {
+        let [x, y, z] = self.axes();
+        std::array::from_fn(|i| {
+            let s = |bit: usize, v: f32| if i & bit == 0 { -v } else { v };
+            self.center + x * s(1, self.half.x) + y * s(2, self.half.y) + z * s(4, self.half.z)
+        })
+    }
+    pub fn local_point(&self, p: Vec3) -> Vec3 {
+        self.rotation.inverse() * (p - self.center)
+    }
+    pub fn contains_point(&self, p: Vec3) -> bool {
+        self.local_point(p).abs().cmple(self.half).all()
+    }
+    // Квадрат расстояния от точки до бокса, 0 — точка внутри
+    pub fn distance_sq(&self, p: Vec3) -> f32 {
+        (self.local_point(p).abs() - self.half).max(Vec3::ZERO).length_squared()
+    }
+    // Луч в собственных координатах бокса, где тот становится AABB -half..half.
+    // Поворот длины не меняет, поэтому расстояния по такому лучу те же, что по исходному.
+    pub fn local_ray(&self, ray: &Ray) -> Ray {
+        let inv = self.rotation.inverse();
+        Ray::new(inv * (ray.origin - self.center), inv * ray.direction)
+    }
+    pub fn ray_hit(&self, ray: &Ray) -> Option<f32> {
+        Aabb::new(-self.half, self.half).ray_hit(&self.local_ray(ray))
+    }
+    // Теорема о разделяющей оси: 3 + 3 оси граней и 9 векторных произведений рёбер.
+    // Касание считается пересечением, как в Aabb::intersects.
+    pub fn intersects(&self, other: &Obb) -> bool {
+        let (a, b) = (self.axes(), other.axes());
+        let t = other.center - self.center;
+        let separated = |axis: Vec3| {
+            // Параллельные рёбра дают нулевую ось — её уже покрыли оси граней
+            if axis.length_squared() < 1e-10 { return false; }
+            let ra = self.half.x * a[0].dot(axis).abs() + self.half.y * a[1].dot(axis).abs() + self.half.z * a[2].dot(axis).abs();
+            let rb = other.half.x * b[0].dot(axis).abs() + other.half.y * b[1].dot(axis).abs() + other.half.z * b[2].dot(axis).abs();
+            t.dot(axis).abs() > ra + rb
+        };
+        if a.iter().chain(b.iter()).any(|&axis| separated(axis)) { return false; }
+        !a.iter().any(|&u| b.iter().any(|&v| separated(u.cross(v))))
+    }
+    pub fn intersects_aabb(&self, b: &Aabb) -> bool {
+        self.intersects(&Obb::from_aabb(b))
+    }
+}
+
+// Контакт двух пересекающихся тел: normal смотрит от первого ко второму, depth — глубина
+// проникновения вдоль неё, points — точки контакта (до четырёх, на середине зоны проникновения)
+#[derive(Clone, Debug)]
+pub struct Manifold {
+    pub normal: Vec3,
+    pub depth: f32,
+    pub points: Vec<Vec3>,
+}
+
+impl Manifold {
+    // Минимальный сдвиг второго тела, разводящий пару до касания; первое двигать на -mtv
+    pub fn mtv(&self) -> Vec3 {
+        self.normal * self.depth
+    }
+}
+
+// AABB против AABB: нормаль — ось и сторона наименьшего выталкивания, точки — углы грани зоны перекрытия.
+// Касание даёт контакт с нулевой глубиной, как и Aabb::intersects.
+pub fn aabb_manifold(a: &Aabb, b: &Aabb) -> Option<Manifold> {
+    let lo = a.min.max(b.min);
+    let hi = a.max.min(b.max);
+    if (hi - lo).cmplt(Vec3::ZERO).any() { return None; }
+
+    // По каждой оси b можно вытолкнуть в плюс или в минус; вложенный бокс уходит через ближнюю грань
+    let (plus, minus) = (a.max - b.min, b.max - a.min);
+    let push = plus.min(minus);
+    let axis = if push.x <= push.y && push.x <= push.z { 0 } else if push.y <= push.z { 1 } else { 2 };
+    let mut normal = Vec3::ZERO;
+    normal[axis] = if plus[axis] <= minus[axis] { 1.0 } else { -1.0 };
+
+    // Грань зоны перекрытия, перпендикулярная нормали, на её середине
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut points: Vec<Vec3> = Vec::with_capacity(4);
+    for (pu, pv) in [(lo[u], lo[v]), (hi[u], lo[v]), (hi[u], hi[v]), (lo[u], hi[v])] {
+        let mut p = Vec3::ZERO;
+        p[axis] = (lo[axis] + hi[axis]) * 0.5;
+        p[u] = pu;
+        p[v] = pv;
+        // Вырожденная грань (ребро или точка) даёт совпадающие углы
+        if !points.contains(&p) { points.push(p); }
+    }
+    Some(Manifold { normal, depth: push[axis], points })
+}
+
+// OBB против OBB: нормаль — ось SAT с наименьшим перекрытием, точки — углы одного бокса внутри другого.
+// Если боксы сцепились рёбрами и углов внутри нет, точка одна — середина между ближайшими углами.
+pub fn obb_manifold(a: &Obb, b: &Obb) -> Option<Manifold> {
+    let (ax, bx) = (a.axes(), b.axes());
+    let t = b.center - a.center;
+    let radius = |o: &Obb, axes: &[Vec3; 3], axis: Vec3| {
+        o.half.x * axes[0].dot(axis).abs() + o.half.y * axes[1].dot(axis).abs() + o.half.z * axes[2].dot(axis).abs()
+    };
+    let edges = ax.iter().flat_map(|&u| bx.iter().map(move |&v| u.cross(v)));
+    let mut best: Option<(f32, Vec3)> = None;
+    for axis in ax.iter().chain(bx.iter()).copied().chain(edges) {
+        // Параллельные рёбра дают нулевую ось, как в Obb::intersects
+        if axis.length_squared() < 1e-10 { continue; }
+        let axis = axis.normalize();
+        let overlap = radius(a, &ax, axis) + radius(b, &bx, axis) - t.dot(axis).abs();
+        if overlap < 0.0 { return None; }
+        if best.is_none_or(|(depth, _)| overlap < depth) {
+            best = Some((overlap, if t.dot(axis) < 0.0 { -axis } else { axis }));
+        }
+    }
+    let (depth, normal) = best?;
+
+    let mut points: Vec<Vec3> = Vec::with_capacity(4);
+    let inside = b.corners().into_iter().filter(|&c| a.contains_point(c))
+        .chain(a.corners().into_iter().filter(|&c| b.contains_point(c)));
+    for p in inside {
+        if points.len() < 4 && !points.contains(&p) { points.push(p); }
+    }
+    if points.is_empty() {
+        let deepest = |o: &Obb, dir: Vec3| o.corners().into_iter().max_by(|p, q| p.dot(dir).total_cmp(&q.dot(dir))).unwrap();
+        points.push((deepest(a, normal) + deepest(b, -normal)) * 0.5);
+    }
+    Some(Manifold { normal, depth, points })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Obb, aabb_manifold, obb_manifold};
+    use crate::Aabb;
+    use crate::Vec3;
+    use crate::ray::Ray;
+    use crate::rng::Rng;
+    use glam::{EulerRot, Quat};
+    use std::f32::consts::PI;
+
+    fn euler(angles: Vec3) -> Quat {
+        Quat::from_euler(EulerRot::XYZ, angles.x, angles.y, angles.z)
+    }
+
+    #[test]
+    fn obb_agrees_with_sampling() {
+        let mut rng = Rng::new(45);
+        for case in 0..2000 {
+            let obb = Obb::new(rng.vec3(-3.0, 3.0), rng.vec3(0.1, 2.0), euler(rng.vec3(-PI, PI)));
+            let min = rng.vec3(-4.0, 4.0);
+            let bbox = Aabb::new(min, min + rng.vec3(0.1, 3.0));
+
+            // Плотный AABB содержит все углы, и каждая его грань касается какого-то угла
+            let tight = obb.aabb();
+            let corners = obb.corners();
+            let eps = Vec3::splat(1e-4);
+            assert!(corners.iter().all(|&c| c.cmpge(tight.min - eps).all() && c.cmple(tight.max + eps).all()), "case {}: corner outside aabb", case);
+            let lo = corners.iter().fold(Vec3::splat(f32::MAX), |m, &c| m.min(c));
+            let hi = corners.iter().fold(Vec3::splat(f32::MIN), |m, &c| m.max(c));
+            assert!((lo - tight.min).abs().max_element() < 1e-4 && (hi - tight.max).abs().max_element() < 1e-4, "case {}: aabb is not tight", case);
+
+            // Общая точка найдена перебором — SAT обязан видеть пересечение; разделены по SAT — общих углов нет
+            let [x, y, z] = obb.axes();
+            let inside = (0..64).map(|_| rng.vec3(-0.99, 0.99))
+                .map(|u| obb.center + x * u.x * obb.half.x + y * u.y * obb.half.y + z * u.z * obb.half.z)
+                .any(|p| p.cmpgt(bbox.min + eps).all() && p.cmplt(bbox.max - eps).all());
+            let hit = obb.intersects_aabb(&bbox);
+            if inside { assert!(hit, "case {}: sampled common point but SAT says disjoint", case); }
+            if !hit {
+                let other = Obb::from_aabb(&bbox);
+                assert!(!corners.iter().any(|&c| other.contains_point(c)), "case {}: SAT separated overlapping boxes", case);
+                assert!(!other.corners().iter().any(|&c| obb.contains_point(c)), "case {}: SAT separated overlapping boxes", case);
+            }
+            assert_eq!(hit, Obb::from_aabb(&bbox).intersects(&obb), "case {}: SAT is not symmetric", case);
+
+            // Точка входа луча лежит на поверхности OBB
+            let ray = Ray::new(rng.vec3(-8.0, 8.0), rng.vec3(-1.0, 1.0) + Vec3::splat(0.01));
+            if let Some(t) = obb.ray_hit(&ray)
+                && t > 0.0 {
+                let p = obb.local_point(ray.origin + ray.direction * t);
+                assert!((p.abs() - obb.half).max_element().abs() < 1e-3, "case {}: ray enters at {} not on the surface", case, p);
+            }
+        }
+    }
+
+    #[test]
+    fn aabb_manifold_separates_pair() {
+        let mut rng = Rng::new(49);
+        for case in 0..2000 {
+            let (min_a, min_b) = (rng.vec3(-3.0, 3.0), rng.vec3(-3.0, 3.0));
+            let a = Aabb::new(min_a, min_a + rng.vec3(0.1, 4.0));
+            let b = Aabb::new(min_b, min_b + rng.vec3(0.1, 4.0));
+            let Some(m) = aabb_manifold(&a, &b) else {
+                assert!(!a.intersects(&b), "case {}: overlapping boxes without contact", case);
+                continue;
+            };
+            assert!(a.intersects(&b), "case {}: contact for disjoint boxes", case);
+            assert!(m.depth >= 0.0 && m.normal.abs().max_element() == 1.0 && m.normal.length_squared() == 1.0, "case {}: bad normal {} or depth {}", case, m.normal, m.depth);
+            assert!(!m.points.is_empty() && m.points.len() <= 4, "case {}: {} contact points", case, m.points.len());
+            let eps = Vec3::splat(1e-4);
+            for p in &m.points {
+                assert!(p.cmpge(a.min.max(b.min) - eps).all() && p.cmple(a.max.min(b.max) + eps).all(), "case {}: point {} outside the overlap", case, p);
+            }
+
+            // Сдвиг на mtv оставляет боксы касающимися, а ни одна грань не выталкивает короче
+            let mtv = m.mtv();
+            let moved = Aabb::new(b.min + mtv, b.max + mtv);
+            let overlap = a.max.min(moved.max) - a.min.max(moved.min);
+            assert!(overlap.min_element().abs() < 1e-4, "case {}: after mtv overlap is {}", case, overlap);
+            let shortest = (a.max - b.min).min(b.max - a.min).min_element();
+            assert!(shortest == m.depth, "case {}: depth {} but a push of {} separates", case, m.depth, shortest);
+        }
+    }
+
+    #[test]
+    fn obb_manifold_separates_pair() {
+        let mut rng = Rng::new(45);
+        let mut touching = 0;
+        for case in 0..2000 {
+            let a = Obb::new(rng.vec3(-1.5, 1.5), rng.vec3(0.2, 1.5), euler(rng.vec3(-PI, PI)));
+            let b = Obb::new(rng.vec3(-1.5, 1.5), rng.vec3(0.2, 1.5), euler(rng.vec3(-PI, PI)));
+            let Some(m) = obb_manifold(&a, &b) else {
+                assert!(!a.intersects(&b), "case {}: overlapping boxes without contact", case);
+                continue;
+            };
+            touching += 1;
+            assert!(a.intersects(&b), "case {}: contact for disjoint boxes", case);
+            assert!(m.depth >= 0.0 && (m.normal.length() - 1.0).abs() < 1e-4, "case {}: bad normal {} or depth {}", case, m.normal, m.depth);
+            assert!(m.normal.dot(b.center - a.center) >= 0.0, "case {}: normal points from b to a", case);
+            assert!(!m.points.is_empty() && m.points.len() <= 4, "case {}: {} contact points", case, m.points.len());
+
+            // Чуть дальше mtv — пара разведена
+            let moved = Obb::new(b.center + m.mtv() + m.normal * 1e-3, b.half, b.rotation);
+            assert!(!a.intersects(&moved), "case {}: still overlapping after mtv {}", case, m.mtv());
+        }
+        assert!(touching > 200, "only {} overlapping pairs", touching);
+
+        // Без поворота — тот же контакт, что и у AABB
+        let (a, b) = (Aabb::new(Vec3::ZERO, Vec3::splat(2.0)), Aabb::new(Vec3::new(1.5, 0.5, 0.5), Vec3::splat(3.0)));
+        let (m, expected) = (obb_manifold(&Obb::from_aabb(&a), &Obb::from_aabb(&b)).unwrap(), aabb_manifold(&a, &b).unwrap());
+        assert_eq!((m.normal, m.depth), (expected.normal, expected.depth));
+    }
+}
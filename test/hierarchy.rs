@@ -0,0 +1,193 @@
+// Иерархия сущностей: оружие в руке игрока, дверь в раме.
+// Ребёнок хранит смещение и поворот относительно родителя (Entity::local_pos / local_rotation), а его
+// pos и rotation остаются мировыми — их пересчитывает родитель, когда двигается сам. Прокси всего
+// поддерева обновляются тем же путём, что и у родителя: сразу одним пакетом (update_position) или через
+// dirty до flush (set_transform). mark_for_deletion удаляет сущность вместе с поддеревом.
+use crate::Vec3;
+use crate::entity::Activity;
+use crate::events::Event;
+use crate::world::World;
+use glam::Quat;
+
+// Мировой поворот ребёнка; None — оба без поворота
+fn combine(parent: Option<Quat>, local: Option<Quat>) -> Option<Quat> {
+    match (parent, local) {
+        (None, None) => None,
+        (p, l) => Some(p.unwrap_or(Quat::IDENTITY) * l.unwrap_or(Quat::IDENTITY)),
+    }
+}
+
+#[rustfmt::skip]
+impl World {
+    pub fn parent_of(&self, id: i32) -> Option<i32> {
+        self.registry.get(id).map(|e| e.parent).filter(|&p| p != -1)
+    }
+    pub fn children(&self, id: i32) -> &[i32] {
+        self.registry.get(id).map_or(&[], |e| &e.children)
+    }
+    // Всё поддерево без самой сущности, родители раньше своих детей
+    pub fn descendants(&self, id: i32) -> Vec<i32> {
+        let mut out = Vec::new();
+        let mut stack = self.children(id).to_vec();
+        while let Some(c) = stack.pop() {
+            out.push(c);
+            stack.extend_from_slice(self.children(c));
+        }
+        out
+    }
+    // Прикрепляет child к parent, не сдвигая его в мире: смещение считается из текущих положений
+    pub fn set_parent(&mut self, child: i32, parent: i32) -> Result<(), String> {
+        if !self.registry.contains(child) {
+            return Err(format!("no entity {}", child));
+        }
+        match self.registry.get(parent) {
+            None => return Err(format!("no entity {}", parent)),
+            Some(p) if p.gameplay.is_dirty => return Err(format!("entity {} is pending deletion", parent)),
+            Some(_) => {}
+        }
+        if child == parent || self.descendants(child).contains(&parent) {
+            return Err(format!("entity {} is in the subtree of {}", parent, child));
+        }
+        self.detach(child);
+        self.registry[parent].children.push(child);
+        self.registry[child].parent = parent;
+        self.sync_local(child);
+        Ok(())
+    }
+    // Отцепляет от родителя, оставляя на месте
+    pub fn detach(&mut self, child: i32) -> bool {
+        let Some(parent) = self.parent_of(child) else { return false };
+        if let Some(p) = self.registry.get_mut(parent) { p.children.retain(|&c| c != child); }
+        self.registry[child].parent = -1;
+        true
+    }
+    // Смещение и поворот относительно родителя; сущность и поддерево уходят в dirty, как при set_transform
+    pub fn set_local_transform(&mut self, id: i32, local_pos: Vec3, local_rotation: Option<Quat>) -> bool {
+        let Some(parent) = self.parent_of(id) else { return false };
+        let e = &mut self.registry[id];
+        e.local_pos = local_pos;
+        e.local_rotation = local_rotation;
+        // Положение самой сущности пересчитывается тем же проходом, что и у братьев — они не сдвинутся
+        self.propagate(parent, true);
+        true
+    }
+    // Сущность сдвинули напрямую: запоминаем её новое смещение относительно родителя
+    pub(crate) fn sync_local(&mut self, id: i32) {
+        let Some(parent) = self.parent_of(id) else { return };
+        let (ppos, prot) = (self.registry[parent].pos, self.registry[parent].rotation);
+        let inv = prot.unwrap_or(Quat::IDENTITY).inverse();
+        let e = &mut self.registry[id];
+        e.local_pos = inv * (e.pos - ppos);
+        e.local_rotation = if prot.is_none() { e.rotation } else { Some(inv * e.rotation.unwrap_or(Quat::IDENTITY)) };
+    }
+    // Пересчитывает мировые положения потомков id. deferred — через dirty, иначе возвращает пакет
+    // (id, смещение) для деревьев — вызывающий применяет его вместе со своими перемещениями.
+    pub(crate) fn propagate(&mut self, id: i32, deferred: bool) -> Vec<(i32, Vec3)> {
+        let mut batch = Vec::new();
+        let mut stack = self.children(id).to_vec();
+        while let Some(c) = stack.pop() {
+            let parent = self.registry[c].parent;
+            let (ppos, prot) = (self.registry[parent].pos, self.registry[parent].rotation);
+            let e = &mut self.registry[c];
+            let pos = ppos + prot.unwrap_or(Quat::IDENTITY) * e.local_pos;
+            let rotation = combine(prot, e.local_rotation);
+            // Не сдвинулся — значит, и его поддерево на месте
+            if pos == e.pos && rotation == e.rotation { continue; }
+            if pos != e.pos { self.events.emit(Event::Moved { id: c, from: e.pos, to: pos }); }
+            let displacement = pos - e.pos;
+            e.pos = pos;
+            e.rotation = rotation;
+            e.world_pos = e.world_pos.map(|_| self.origin + pos.as_dvec3());
+            if e.activity == Activity::Sleeping { e.activity = Activity::Awake; }
+            e.idle_steps = 0;
+            self.recently_moved.insert(c);
+            if deferred || self.dirty.contains_key(&c) {
+                *self.dirty.entry(c).or_insert(Vec3::ZERO) += displacement;
+            } else {
+                batch.push((c, displacement));
+            }
+            stack.extend_from_slice(&e.children);
+        }
+        batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Vec3;
+    use crate::validate::validate_world;
+    use crate::world::World;
+    use glam::Quat;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).abs().max_element() < 1e-5
+    }
+
+    #[test]
+    fn attach_keeps_the_world_place_and_rejects_cycles() {
+        let mut world = World::new();
+        let hand = world.create_entity(Vec3::new(1.0, 0.0, 0.0), Vec3::ONE, 1, 0);
+        let sword = world.create_entity(Vec3::new(3.0, 0.0, 0.0), Vec3::ONE, 1, 0);
+        let gem = world.create_entity(Vec3::new(4.0, 0.0, 0.0), Vec3::ONE, 1, 0);
+        world.set_parent(sword, hand).unwrap();
+        world.set_parent(gem, sword).unwrap();
+        assert_eq!((world.registry[sword].pos, world.registry[sword].local_pos), (Vec3::new(3.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0)));
+        assert_eq!(world.descendants(hand), [sword, gem]);
+
+        assert!(world.set_parent(hand, gem).is_err() && world.set_parent(hand, hand).is_err());
+        assert!(world.set_parent(hand, -1).is_err());
+        // Перецепка убирает ребёнка из прежнего родителя
+        world.set_parent(gem, hand).unwrap();
+        assert_eq!((world.children(hand), world.children(sword)), (&[sword, gem][..], &[][..]));
+        assert!(world.detach(gem) && !world.detach(gem));
+        assert_eq!((world.parent_of(gem), world.registry[gem].pos), (None, Vec3::new(4.0, 0.0, 0.0)));
+        validate_world(&world).unwrap();
+    }
+
+    #[test]
+    fn children_follow_moves_and_turns() {
+        let mut world = World::new();
+        let door = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 0);
+        let knob = world.create_entity(Vec3::new(1.0, 0.0, 0.0), Vec3::ONE, 1, 0);
+        world.set_parent(knob, door).unwrap();
+
+        world.update_position(door, Vec3::new(0.0, 0.0, 5.0));
+        assert_eq!(world.registry[knob].pos, Vec3::new(1.0, 0.0, 5.0));
+        validate_world(&world).unwrap();
+
+        // Поворот родителя поворачивает и смещение ребёнка; дерево догоняет на flush
+        let quarter = Quat::from_rotation_y(FRAC_PI_2);
+        world.set_rotation(door, Some(quarter));
+        assert!(close(world.registry[knob].pos, Vec3::new(0.0, 0.0, 4.0)), "{}", world.registry[knob].pos);
+        assert_eq!(world.registry[knob].rotation, Some(quarter));
+        assert!(world.dirty.contains_key(&knob));
+        world.flush();
+        validate_world(&world).unwrap();
+
+        // Новое локальное смещение — в осях родителя
+        assert!(world.set_local_transform(knob, Vec3::new(0.0, 0.0, 2.0), None));
+        assert!(close(world.registry[knob].pos, Vec3::new(2.0, 0.0, 5.0)), "{}", world.registry[knob].pos);
+        assert!(!world.set_local_transform(door, Vec3::ZERO, None), "root has no parent");
+        world.flush();
+        validate_world(&world).unwrap();
+    }
+
+    #[test]
+    fn deleting_a_parent_takes_the_subtree() {
+        let mut world = World::new();
+        let cart = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 0);
+        let wheel = world.create_entity(Vec3::X, Vec3::ONE, 1, 0);
+        let bolt = world.create_entity(Vec3::X, Vec3::ONE, 1, 0);
+        let other = world.create_entity(Vec3::Z, Vec3::ONE, 1, 0);
+        world.set_parent(wheel, cart).unwrap();
+        world.set_parent(bolt, wheel).unwrap();
+
+        world.mark_for_deletion(cart);
+        assert!(world.set_parent(other, cart).is_err(), "pending deletion");
+        world.cleanup();
+        assert_eq!(world.registry.sorted_ids(), [other]);
+        assert!(world.children(cart).is_empty() && world.parent_of(other).is_none());
+        validate_world(&world).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+// Выбор предмета для взаимодействия: на что смотрит актёр, а если луч ничего не нашёл — что рядом в конусе взгляда.
+// Интерактивные сущности — те, у кого задан on_interact. Стены (слои occluders) закрывают обзор.
+use crate::Vec3;
+use crate::events::Event;
+use crate::filter::QueryFilter;
+use crate::Aabb;
+use crate::ray::Ray;
+use crate::world::World;
+
+pub struct InteractionSystem {
+    pub max_range: f32,  // дальше этого не дотянуться
+    pub cone_angle: f32, // половина угла конуса для поиска рядом, радианы
+    pub occluders: i32,  // слои, через которые не видно и не дотянуться
+}
+
+pub struct InteractionTarget {
+    pub id: i32,
+    pub distance: f32,
+    pub handler: String, // on_interact цели — подпись для подсказки "нажмите E"
+    pub by_ray: bool,    // найдено прямым взглядом, а не по конусу
+}
+
+impl InteractionSystem {
+    // По умолчанию: 3 единицы, конус 30°, обзор закрывает слой static
+    pub fn new(world: &World) -> Self {
+        Self { max_range: 3.0, cone_angle: 30f32.to_radians(), occluders: world.layers.bit("static").unwrap_or(0) }
+    }
+
+    fn target(world: &World, id: i32, distance: f32, by_ray: bool) -> InteractionTarget {
+        let handler = world.registry[id].on_interact.clone().unwrap_or_default();
+        InteractionTarget { id, distance, handler, by_ray }
+    }
+
+    // Что-то из occluders на отрезке eye..point ближе, чем сама цель; актёр, из которого смотрят, не в счёт
+    fn blocked(&self, world: &World, eye: Vec3, point: Vec3, actor: i32, target: i32) -> bool {
+        let filter = QueryFilter::any().ignore(actor).ignore(target).with(|e| e.category & self.occluders != 0);
+        world.raycast(eye, point, &filter).is_some()
+    }
+
+    // Цель для подсказки; actor в поиске не участвует
+    pub fn find_target(&self, world: &World, actor: i32, eye: Vec3, dir: Vec3) -> Option<InteractionTarget> {
+        let dir = dir.normalize_or_zero();
+        if dir == Vec3::ZERO { return None; }
+
+        // 1. Прямой взгляд: ближайшее попадание, пока не упрёмся в стену
+        let ray = Ray::new(eye, dir);
+        for (t, id) in world.ray_hits(&ray, &QueryFilter::any().ignore(actor)) {
+            if t > self.max_range { break; }
+            let e = &world.registry[id];
+            if e.on_interact.is_some() { return Some(Self::target(world, id, t, true)); }
+            if e.category & self.occluders != 0 { break; }
+        }
+
+        // 2. Рядом в конусе: меньший угол важнее, при равных — ближе
+        let reach = Vec3::splat(self.max_range);
+        let filter = QueryFilter::any().ignore(actor).with(|e| e.on_interact.is_some());
+        let mut near = Vec::new();
+        world.query(&Aabb::new(eye - reach, eye + reach), &filter, &mut near);
+        near.sort_unstable();
+
+        let mut best: Option<(f32, i32, f32)> = None;
+        for id in near {
+            let e = &world.registry[id];
+            let distance = e.distance_sq(eye).sqrt();
+            if distance > self.max_range { continue; }
+            let angle = if distance == 0.0 { 0.0 } else { dir.angle_between(e.pos - eye) };
+            if angle > self.cone_angle { continue; }
+            if self.blocked(world, eye, e.pos, actor, id) { continue; }
+            let score = angle / self.cone_angle + distance / self.max_range;
+            if best.is_none_or(|(s, _, _)| score < s) { best = Some((score, id, distance)); }
+        }
+        best.map(|(_, id, distance)| Self::target(world, id, distance, false))
+    }
+
+    // Действие "взаимодействовать": Interacted доставляется цели сразу, чтобы её обработчик сработал в этом кадре.
+    // Остальная очередь событий не трогается.
+    pub fn interact(&self, world: &mut World, actor: i32, eye: Vec3, dir: Vec3) -> Option<InteractionTarget> {
+        let target = self.find_target(world, actor, eye, dir)?;
+        world.deliver(&Event::Interacted { target: target.id, actor });
+        Some(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InteractionSystem;
+    use crate::Vec3;
+    use crate::events::Event;
+    use crate::world::World;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // Актёр в начале координат и кнопка с обработчиком "press"
+    fn scene(button: Vec3) -> (World, i32, i32) {
+        let mut world = World::new();
+        let player = world.layers.bit("player").unwrap();
+        let actor = world.create_entity(Vec3::ZERO, Vec3::ONE, player, 0);
+        let target = world.create_entity(button, Vec3::splat(0.5), player, 0);
+        world.registry[target].on_interact = Some("press".to_string());
+        (world, actor, target)
+    }
+
+    #[test]
+    fn ray_pick_beats_cone() {
+        let (mut world, actor, target) = scene(Vec3::new(2.0, 0.0, 0.0));
+        let player = world.registry[actor].category;
+        let side = world.create_entity(Vec3::new(1.5, 0.0, 0.6), Vec3::splat(0.5), player, 0);
+        world.registry[side].on_interact = Some("press".to_string());
+        let system = InteractionSystem::new(&world);
+
+        let t = system.find_target(&world, actor, Vec3::ZERO, Vec3::X).unwrap();
+        assert_eq!((t.id, t.by_ray, t.handler.as_str()), (target, true, "press"));
+        assert!((t.distance - 1.75).abs() < 1e-5, "distance {}", t.distance);
+    }
+
+    #[test]
+    fn cone_fallback_and_max_range() {
+        let (world, actor, target) = scene(Vec3::new(0.0, 0.0, 2.0));
+        let system = InteractionSystem::new(&world);
+
+        // Луч проходит мимо, но кнопка в конусе взгляда
+        let t = system.find_target(&world, actor, Vec3::ZERO, Vec3::new(0.3, 0.0, 1.0)).unwrap();
+        assert_eq!((t.id, t.by_ray), (target, false));
+        // Вне конуса и за спиной — ничего
+        assert!(system.find_target(&world, actor, Vec3::ZERO, Vec3::new(1.0, 0.0, 0.2)).is_none());
+        assert!(system.find_target(&world, actor, Vec3::ZERO, Vec3::NEG_Z).is_none());
+
+        let short = InteractionSystem { max_range: 1.5, ..InteractionSystem::new(&world) };
+        assert!(short.find_target(&world, actor, Vec3::ZERO, Vec3::Z).is_none());
+    }
+
+    #[test]
+    fn walls_block_but_the_actor_does_not() {
+        let (mut world, actor, target) = scene(Vec3::new(0.0, 0.0, 2.0));
+        let system = InteractionSystem::new(&world);
+        let dir = Vec3::new(0.3, 0.0, 1.0);
+
+        // Актёр сам из слоя occluders (турель, вмурованная в стену) — себе обзор он не закрывает
+        world.registry[actor].category |= system.occluders;
+        assert_eq!(system.find_target(&world, actor, Vec3::ZERO, dir).map(|t| t.id), Some(target));
+
+        let wall = world.create_entity(Vec3::new(0.0, 0.0, 1.2), Vec3::new(3.0, 3.0, 0.2), system.occluders, 0);
+        assert!(system.find_target(&world, actor, Vec3::ZERO, dir).is_none());
+        assert!(system.find_target(&world, actor, Vec3::ZERO, Vec3::Z).is_none());
+        world.mark_for_deletion(wall);
+        world.cleanup();
+        assert!(system.find_target(&world, actor, Vec3::ZERO, Vec3::Z).is_some());
+    }
+
+    #[test]
+    fn interact_delivers_only_its_event() {
+        let (mut world, actor, target) = scene(Vec3::new(2.0, 0.0, 0.0));
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        world.events.register("press", move |_, event| {
+            assert!(matches!(event, Event::Interacted { .. }));
+            counter.set(counter.get() + 1);
+        });
+        let queued = world.events.pending().len();
+        assert!(queued > 0);
+
+        let system = InteractionSystem::new(&world);
+        assert_eq!(system.interact(&mut world, actor, Vec3::ZERO, Vec3::X).map(|t| t.id), Some(target));
+        assert_eq!(calls.get(), 1);
+        // Spawned и прочее ждут своего dispatch, а Interacted второй раз не приходит
+        assert_eq!(world.events.pending().len(), queued);
+        let delivered = world.dispatch_events();
+        assert_eq!(delivered.len(), queued);
+        assert_eq!(calls.get(), 1);
+    }
+}
@@ -0,0 +1,146 @@
+// Именованные слои столкновений и матрица "кто кого видит".
+//
+// Entity::category — битовая маска слоёв, к которым сущность принадлежит (обычно один бит).
+// Entity::mask — слои, которые сущность сама ищет своими запросами.
+// Матрица задаёт, какие слои вообще могут находить друг друга: row(a) — слои, которые видит слой a.
+// Она может быть несимметричной (например, триггер видит игрока, а игрок триггер — нет).
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const MAX_LAYERS: usize = 32;
+
+pub struct CollisionLayers {
+    names: Vec<String>, // индекс = номер бита
+    rows: Vec<i32>,     // rows[i] — маска слоёв, которые видит слой i
+}
+
+impl CollisionLayers {
+    pub fn new() -> Self {
+        Self { names: Vec::new(), rows: Vec::new() }
+    }
+    // Раскладка демо-сцены и save.json: static = 1, trigger = 2, player = 4; игрок видит стены и триггеры и наоборот
+    pub fn with_defaults() -> Self {
+        let mut layers = Self::new();
+        let stat = layers.add("static").unwrap();
+        let trigger = layers.add("trigger").unwrap();
+        let player = layers.add("player").unwrap();
+        layers.set_collides(player, stat, true);
+        layers.set_collides(player, trigger, true);
+        layers
+    }
+    pub fn add(&mut self, name: &str) -> Result<i32, String> {
+        if let Some(bit) = self.bit(name) { return Ok(bit); }
+        if self.names.len() == MAX_LAYERS {
+            return Err(format!("cannot add layer \"{}\": all {} layers are used", name, MAX_LAYERS));
+        }
+        if name.is_empty() || name.contains(['|', ',', ' ']) {
+            return Err(format!("bad layer name \"{}\"", name));
+        }
+        self.names.push(name.to_string());
+        self.rows.push(0);
+        Ok(1 << (self.names.len() - 1))
+    }
+    pub fn bit(&self, name: &str) -> Option<i32> {
+        self.names.iter().position(|n| n == name).map(|i| 1 << i)
+    }
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+    // "static|trigger" -> 0b011; число тоже принимается как есть
+    pub fn parse_mask(&self, text: &str) -> Result<i32, String> {
+        if let Ok(v) = text.parse::<i32>() { return Ok(v); }
+        let mut mask = 0;
+        for part in text.split('|') {
+            mask |= self.bit(part).ok_or(format!("unknown layer \"{}\"", part))?;
+        }
+        Ok(mask)
+    }
+    // 0b011 -> "static|trigger"; биты без имени печатаются числом
+    pub fn describe(&self, mask: i32) -> String {
+        let mut parts = Vec::new();
+        let mut unnamed = 0;
+        for bit in 0..MAX_LAYERS {
+            if mask & (1 << bit) == 0 { continue; }
+            match self.names.get(bit) {
+                Some(n) => parts.push(n.clone()),
+                None => unnamed |= 1 << bit,
+            }
+        }
+        if unnamed != 0 { parts.push(unnamed.to_string()); }
+        if parts.is_empty() { "none".to_string() } else { parts.join("|") }
+    }
+    // Слой(и) a видят слой(и) b (в одну сторону)
+    pub fn set_sees(&mut self, a: i32, b: i32, on: bool) {
+        for i in 0..self.rows.len() {
+            if a & (1 << i) == 0 { continue; }
+            if on { self.rows[i] |= b; } else { self.rows[i] &= !b; }
+        }
+    }
+    // Симметричная пара
+    pub fn set_collides(&mut self, a: i32, b: i32, on: bool) {
+        self.set_sees(a, b, on);
+        self.set_sees(b, a, on);
+    }
+    // Маска всех слоёв, которые видит хотя бы один слой из category
+    pub fn row(&self, category: i32) -> i32 {
+        (0..self.rows.len()).filter(|i| category & (1 << i) != 0).fold(0, |acc, i| acc | self.rows[i])
+    }
+    pub fn sees(&self, a: i32, b: i32) -> bool {
+        self.row(a) & b != 0
+    }
+}
+
+// Фильтр запроса по слоям: кто спрашивает (category) и кого ищет (mask).
+// Сущность проходит, если она в одном из слоёв mask и матрица разрешает category видеть её слой.
+#[derive(Clone, Copy, Debug)]
+pub struct LayerFilter {
+    pub category: i32,
+    pub mask: i32,
+}
+
+impl LayerFilter {
+    // Без фильтрации по слоям — для отладки и инструментов
+    pub const ANY: LayerFilter = LayerFilter { category: 0, mask: !0 };
+
+    pub fn new(category: i32, mask: i32) -> Self {
+        Self { category, mask }
+    }
+    pub fn accepts(&self, layers: &CollisionLayers, category: i32) -> bool {
+        if self.mask == !0 && self.category == 0 { return true; }
+        self.mask & category != 0 && (self.category == 0 || layers.sees(self.category, category))
+    }
+}
+
+// Представление в save-файле: имена по порядку битов и для каждого слоя — список слоёв, которые он видит
+#[derive(Serialize, Deserialize)]
+pub struct SavedLayers {
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub sees: BTreeMap<String, Vec<String>>,
+}
+
+impl CollisionLayers {
+    pub fn to_saved(&self) -> SavedLayers {
+        let mut sees = BTreeMap::new();
+        for (i, name) in self.names.iter().enumerate() {
+            let row: Vec<String> = (0..self.names.len())
+                .filter(|j| self.rows[i] & (1 << j) != 0)
+                .map(|j| self.names[j].clone())
+                .collect();
+            if !row.is_empty() { sees.insert(name.clone(), row); }
+        }
+        SavedLayers { names: self.names.clone(), sees }
+    }
+    pub fn from_saved(saved: &SavedLayers) -> Result<Self, String> {
+        let mut layers = Self::new();
+        for name in &saved.names { layers.add(name)?; }
+        for (name, row) in &saved.sees {
+            let a = layers.bit(name).ok_or(format!("layers.sees: unknown layer \"{}\"", name))?;
+            for other in row {
+                let b = layers.bit(other).ok_or(format!("layers.sees.{}: unknown layer \"{}\"", name, other))?;
+                layers.set_sees(a, b, true);
+            }
+        }
+        Ok(layers)
+    }
+}
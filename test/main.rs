@@ -1,110 +1,344 @@
+use crate::commands::WorldCommands;
+use crate::console::Console;
+use crate::filter::QueryFilter;
+use crate::render::{Camera, RenderOptions};
+use crate::rng::Rng;
+use crate::svg::SvgOptions;
 use crate::world::World;
 use aabb::Aabb;
 use dynbvh::DynamicBvh;
 use glam::Vec3;
 use stack::Stack;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Instant;
 mod aabb;
+mod activity;
+mod bvhcheck;
+mod commands;
+mod components;
+mod contacts;
+mod console;
 mod dynbvh;
 mod entity;
+mod events;
+mod filter;
+#[cfg(test)]
+mod fuzz;
+mod geometry;
+mod hierarchy;
+mod interaction;
+mod layers;
 mod node;
+mod persistency;
+mod physics;
 mod ray;
+mod render;
+mod rng;
 mod stack;
+mod store;
+mod svg;
+mod validate;
 mod world;
+#[cfg(test)]
+mod proptests;
 
-fn main() {
+const USAGE: &str = "\
+usage: project_BVH <command> [args]
+
+commands:
+  load <save.json>                      load a save file and print stats
+  validate <save.json>                  check tree and registry invariants
+  bench [entities] [frames] [seed]      generated scene: build, move, query, ray timings
+  render <save.json> <out> [options]    picture of the world: .png/.ppm (3D) or .svg (top-down)
+      --size WxH          image size (png/ppm), default 640x480
+      --depth N           draw BVH nodes down to depth N (-1 = all, default)
+      --no-nodes          do not draw BVH nodes
+      --query MIN MAX     overlay a query box (x,y,z x,y,z) and highlight hits
+  replay <ops.txt> [save.json]          run a console script, optionally on top of a save file
+  console [save.json]                   interactive console on stdin
+  help                                  this text
+
+exit codes: 0 success, 1 check/script failure, 2 bad usage or unreadable input";
+
+// Ошибки CLI: неверные аргументы/файлы (2) отдельно от упавших проверок (1)
+enum Failure {
+    Usage(String),
+    Check(String),
+}
+
+fn usage<T>(msg: impl Into<String>) -> Result<T, Failure> {
+    Err(Failure::Usage(msg.into()))
+}
+
+fn load(path: &str) -> Result<World, Failure> {
+    persistency::load_world(Path::new(path)).map_err(Failure::Usage)
+}
+
+fn parse_vec3(tok: &str) -> Result<Vec3, Failure> {
+    let v: Vec<f32> = tok.split(',').filter_map(|p| p.parse().ok()).collect();
+    if v.len() != 3 { return usage(format!("expected vector x,y,z, got \"{}\"", tok)); }
+    Ok(Vec3::new(v[0], v[1], v[2]))
+}
+
+fn parse_num<T: std::str::FromStr>(tok: Option<&String>, default: T, what: &str) -> Result<T, Failure> {
+    match tok {
+        None => Ok(default),
+        Some(t) => t.parse().or_else(|_| usage(format!("{}: expected a number, got \"{}\"", what, t))),
+    }
+}
+
+fn cmd_load(args: &[String]) -> Result<(), Failure> {
+    let [path] = args else { return usage("usage: load <save.json>") };
+    let world = load(path)?;
+    print!("{}", console::stats_report(&world));
+    Ok(())
+}
+
+fn cmd_validate(args: &[String]) -> Result<(), Failure> {
+    let [path] = args else { return usage("usage: validate <save.json>") };
+    let world = load(path)?;
+    validate::validate_world(&world).map_err(Failure::Check)?;
+    println!("ok: {} entities", world.registry.len());
+    Ok(())
+}
+
+fn cmd_bench(args: &[String]) -> Result<(), Failure> {
+    if args.len() > 3 { return usage("usage: bench [entities] [frames] [seed]"); }
+    let entities: usize = parse_num(args.first(), 10_000, "entities")?;
+    let frames: usize = parse_num(args.get(1), 60, "frames")?;
+    let seed: u64 = parse_num(args.get(2), 1, "seed")?;
+
+    let mut rng = Rng::new(seed);
+    let extent = (entities as f32).sqrt() * 4.0;
     let mut world = World::new();
 
-    println!("=== ИНИЦИАЛИЗАЦИЯ МИРА ===");
-
-    // Константы слоев (для наглядности)
-    const LAYER_NONE: i32 = 0;
-    const LAYER_STATIC: i32 = 1;
-    const LAYER_TRIGGER: i32 = 2;
-    const LAYER_PLAYER: i32 = 4;
-
-    // 1. Создаем стену
-    world.create_entity(
-        Vec3::new(10.0, 0.0, 0.0),
-        Vec3::new(1.0, 10.0, 10.0),
-        LAYER_STATIC,
-        LAYER_NONE,
-    );
-
-    // 2. Создаем ядовитую зону
-    let poison_zone = world.create_entity(
-        Vec3::new(5.0, 0.0, 0.0),
-        Vec3::new(2.0, 2.0, 2.0),
-        LAYER_TRIGGER,
-        LAYER_NONE,
-    );
-    if let Some(e) = world.registry.get_mut(&poison_zone) {
-        e.on_trigger = Some(Box::new(|id| {
-            println!("  [EVENT]: Объект {} вступил в ЯДОВИТУЮ ЗОНУ!", id);
-        }));
+    let t = Instant::now();
+    let ids: Vec<i32> = (0..entities)
+        .map(|i| {
+            let pos = rng.vec3(-extent, extent) * Vec3::new(1.0, 0.1, 1.0);
+            let cat = 1 << (i % 3);
+            world.create_entity(pos, rng.vec3(0.5, 3.0), cat, world.layers.row(cat))
+        })
+        .collect();
+    println!("build      {:>8} entities  {:>10.3} ms", entities, t.elapsed().as_secs_f64() * 1e3);
+
+    // Каждый кадр двигается четверть сцены
+    let movers = &ids[..entities / 4];
+    let mut velocity: Vec<Vec3> = movers.iter().map(|_| rng.vec3(-0.3, 0.3) * Vec3::new(1.0, 0.0, 1.0)).collect();
+    let t = Instant::now();
+    for _ in 0..frames {
+        for (i, &id) in movers.iter().enumerate() {
+            if rng.below(50) == 0 { velocity[i] = rng.vec3(-0.3, 0.3) * Vec3::new(1.0, 0.0, 1.0); }
+            let pos = world.registry[id].pos + velocity[i];
+            world.update_position(id, pos);
+        }
+    }
+    let elapsed = t.elapsed().as_secs_f64() * 1e3;
+    println!("move       {:>8} x {:<6} {:>10.3} ms  ({:.3} ms/frame)", movers.len(), frames, elapsed, elapsed / frames.max(1) as f64);
+
+    let t = Instant::now();
+    for _ in 0..frames {
+        let batch: Vec<(i32, Vec3)> = movers.iter().enumerate().map(|(i, &id)| (id, world.registry[id].pos + velocity[i])).collect();
+        world.update_positions(&batch);
+    }
+    let elapsed = t.elapsed().as_secs_f64() * 1e3;
+    println!("batch move {:>8} x {:<6} {:>10.3} ms  ({:.3} ms/frame)", movers.len(), frames, elapsed, elapsed / frames.max(1) as f64);
+
+    // Две системы пишут каждая в свой буфер, мир применяет их вместе
+    let t = Instant::now();
+    for _ in 0..frames {
+        let (mut first, mut second) = (WorldCommands::new(), WorldCommands::new());
+        for (i, &id) in movers.iter().enumerate() {
+            let buffer = if i % 2 == 0 { &mut first } else { &mut second };
+            buffer.move_to(id, world.registry[id].pos + velocity[i]);
+        }
+        first.append(&mut second);
+        world.apply_commands(first);
     }
+    let elapsed = t.elapsed().as_secs_f64() * 1e3;
+    println!("deferred   {:>8} x {:<6} {:>10.3} ms  ({:.3} ms/frame)", movers.len(), frames, elapsed, elapsed / frames.max(1) as f64);
 
-    // 3. Создаем рычаг
-    let lever_id = world.create_entity(
-        Vec3::new(8.0, 0.0, 2.0),
-        Vec3::new(0.5, 0.5, 0.5),
-        LAYER_TRIGGER,
-        LAYER_NONE,
-    );
-    if let Some(e) = world.registry.get_mut(&lever_id) {
-        e.on_interact = Some(Box::new(|| {
-            println!("  [INTERACT]: Рычаг нажат! Секретная дверь открыта.");
-        }));
+    let queries = 10_000;
+    let mut hits = 0;
+    let mut out = Vec::new();
+    let t = Instant::now();
+    for _ in 0..queries {
+        let min = rng.vec3(-extent, extent) * Vec3::new(1.0, 0.1, 1.0);
+        out.clear();
+        world.query(&Aabb::new(min, min + rng.vec3(0.5, 8.0)), &QueryFilter::any(), &mut out);
+        hits += out.len();
     }
+    println!("query      {:>8}           {:>10.3} ms  ({} hits)", queries, t.elapsed().as_secs_f64() * 1e3, hits);
 
-    // 4. Создаем игрока
-    let player_id = world.create_entity(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.6, 1.8, 0.6),
-        LAYER_PLAYER,
-        LAYER_STATIC | LAYER_TRIGGER,
-    );
-
-    println!("\n=== ТЕСТ 1: ДВИЖЕНИЕ СКВОЗЬ ТРИГГЕР ===");
-    for x in [0.0, 2.0, 4.0, 6.0] {
-        world.update_position(player_id, Vec3::new(x, 0.0, 0.0));
-        println!("Игрок переместился в x={:.1}", x);
-
-        let player_aabb = world.registry[&player_id].get_aabb();
-        let mut nearby = Vec::new();
-        world.query(&player_aabb, &mut nearby);
-
-        for id in nearby {
-            if id != player_id {
-                // Извлекаем колбэк временно, чтобы избежать проблем с Borrow Checker
-                // Или используем Interior Mutability. Здесь проще всего взять ссылку.
-                if let Some(entity) = world.registry.get_mut(&id) {
-                    if let Some(ref mut callback) = entity.on_trigger {
-                        callback(player_id);
-                    }
+    let rays = 10_000;
+    let mut hits = 0;
+    let t = Instant::now();
+    for _ in 0..rays {
+        let mut dir = rng.vec3(-1.0, 1.0);
+        if dir.cmpeq(Vec3::ZERO).any() { dir += Vec3::splat(0.01); }
+        let ray = ray::Ray::new(rng.vec3(-extent, extent) * Vec3::new(1.0, 0.1, 1.0), dir);
+        hits += world.ray_hits(&ray, &QueryFilter::any()).len();
+    }
+    println!("ray        {:>8}           {:>10.3} ms  ({} hits)", rays, t.elapsed().as_secs_f64() * 1e3, hits);
+
+    print!("{}", console::stats_report(&world));
+    validate::validate_world(&world).map_err(Failure::Check)
+}
+
+fn cmd_render(args: &[String]) -> Result<(), Failure> {
+    let (Some(save), Some(out)) = (args.first(), args.get(1)) else {
+        return usage("usage: render <save.json> <out.png|out.ppm|out.svg> [options]");
+    };
+    let world = load(save)?;
+    let mut options = RenderOptions::default();
+    let mut rest = args[2..].iter();
+    while let Some(flag) = rest.next() {
+        match flag.as_str() {
+            "--size" => {
+                let v = rest.next().map(String::as_str).unwrap_or("");
+                let Some((w, h)) = v.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?))) else {
+                    return usage(format!("--size: expected WxH, got \"{}\"", v));
+                };
+                if w == 0 || h == 0 {
+                    return usage(format!("--size: image must be at least 1x1, got \"{}\"", v));
                 }
+                options.width = w;
+                options.height = h;
             }
+            "--depth" => options.max_node_depth = parse_num(rest.next(), -1, "--depth")?,
+            "--no-nodes" => options.draw_nodes = false,
+            "--query" => {
+                let (Some(min), Some(max)) = (rest.next(), rest.next()) else { return usage("--query: expected MIN MAX") };
+                let q = Aabb::new(parse_vec3(min)?, parse_vec3(max)?);
+                world.query(&q, &QueryFilter::any(), &mut options.highlight);
+                options.query = Some(q);
+            }
+            other => return usage(format!("render: unknown option \"{}\"", other)),
         }
     }
 
-    println!("\n=== ТЕСТ 2: ВЗАИМОДЕЙСТВИЕ ===");
-    // Допустим, мы просто ищем объект в точке (Raycast требует отдельной реализации пересечения луча и AABB)
-    let interact_pos = Vec3::new(8.0, 0.0, 2.0);
-    let mut interact_results = Vec::new();
-    world.query(
-        &Aabb::new(interact_pos - 0.1, interact_pos + 0.1),
-        &mut interact_results,
-    );
-
-    for id in interact_results {
-        if let Some(entity) = world.registry.get(&id) {
-            if let Some(ref callback) = entity.on_interact {
-                println!("Найден объект: {}", id);
-                callback();
-            }
+    let path = Path::new(out);
+    let written = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("svg")) {
+        let svg_options = SvgOptions { draw_nodes: options.draw_nodes, query: options.query, ..Default::default() };
+        svg::write_svg(&world, &svg_options, path)
+    } else {
+        let roots: Vec<Aabb> = world.trees().iter()
+            .filter(|(_, tree)| tree.root != -1)
+            .map(|(_, tree)| tree.nodes[tree.root as usize].bbox)
+            .collect();
+        let bounds = roots.iter().copied().reduce(|a, b| Aabb::union(&a, &b))
+            .unwrap_or(Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0)));
+        render::render(&world, &Camera::overview(&bounds), &options).save(path)
+    };
+    written.map_err(|e| Failure::Usage(format!("{}: {}", out, e)))?;
+    println!("wrote {}", out);
+    Ok(())
+}
+
+fn cmd_replay(args: &[String]) -> Result<(), Failure> {
+    let (script, world) = match args {
+        [script] => (script, World::new()),
+        [script, save] => (script, load(save)?),
+        _ => return usage("usage: replay <ops.txt> [save.json]"),
+    };
+    let mut console = Console::new(world);
+    let errors = console.run_script(Path::new(script), &mut std::io::stdout().lock()).map_err(Failure::Usage)?;
+    if errors > 0 { return Err(Failure::Check(format!("{} command(s) failed", errors))); }
+    Ok(())
+}
+
+fn cmd_console(args: &[String]) -> Result<(), Failure> {
+    let world = match args {
+        [] => World::new(),
+        [save] => load(save)?,
+        _ => return usage("usage: console [save.json]"),
+    };
+    let mut console = Console::new(world);
+    eprintln!("type help for commands, quit to exit");
+    console.run(std::io::stdin().lock(), &mut std::io::stdout().lock(), true, false)
+        .map_err(|e| Failure::Usage(e.to_string()))?;
+    Ok(())
+}
+
+// Код выхода для аргументов без имени программы
+fn run(args: &[String]) -> u8 {
+    let Some(cmd) = args.first() else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+    let rest = &args[1..];
+    let result = match cmd.as_str() {
+        "load" => cmd_load(rest),
+        "validate" => cmd_validate(rest),
+        "bench" => cmd_bench(rest),
+        "render" => cmd_render(rest),
+        "replay" => cmd_replay(rest),
+        "console" => cmd_console(rest),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => usage(format!("unknown command \"{}\"\n\n{}", other, USAGE)),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(Failure::Check(msg)) => {
+            eprintln!("error: {}", msg);
+            1
         }
+        Err(Failure::Usage(msg)) => {
+            eprintln!("error: {}", msg);
+            2
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    ExitCode::from(run(&args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use std::path::PathBuf;
+
+    fn cli(args: &[&str]) -> u8 {
+        run(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+    }
+
+    fn temp(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("bvh-cli-{}-{}", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        path.to_string_lossy().into_owned()
     }
 
-    world.clear_all();
-    println!("\n[LOG]: All entities removed. Root: {}", world.bvh.root);
-    println!("\n=== ВСЕ ТЕСТЫ ЗАВЕРШЕНЫ ===");
+    #[test]
+    fn exit_codes() {
+        let save = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("save.json");
+        let save = save.to_str().unwrap();
+        assert_eq!(cli(&["help"]), 0);
+        assert_eq!(cli(&["load", save]), 0);
+        assert_eq!(cli(&["validate", save]), 0);
+        assert_eq!(cli(&["bench", "64", "2", "7"]), 0);
+
+        let good = temp("good.txt", "spawn 0,0,0 1,1,1 1 1\nvalidate\n");
+        let bad = temp("bad.txt", "spawn 0,0,0 1,1,1 1 1\nmove 9 0,0,0\n");
+        assert_eq!(cli(&["replay", &good, save]), 0);
+        assert_eq!(cli(&["replay", &bad]), 1, "failed script command");
+
+        // 2 — вызов неверен или вход не прочитать
+        assert_eq!(cli(&[]), 2);
+        assert_eq!(cli(&["frobnicate"]), 2);
+        assert_eq!(cli(&["load"]), 2);
+        assert_eq!(cli(&["load", "/nonexistent/save.json"]), 2);
+        assert_eq!(cli(&["validate", &good]), 2, "not JSON");
+        assert_eq!(cli(&["bench", "many"]), 2);
+        assert_eq!(cli(&["render", save, "out.png", "--size", "big"]), 2);
+        assert_eq!(cli(&["render", save, "out.png", "--size", "0x0"]), 2);
+        assert_eq!(cli(&["render", save, "out.png", "--size", "64x0"]), 2);
+        assert_eq!(cli(&["replay", "/nonexistent/ops.txt"]), 2);
+        for path in [good, bad] { std::fs::remove_file(path).unwrap(); }
+    }
 }
@@ -9,4 +9,5 @@ pub struct Node {
     pub height: i32,       // = 0;
     pub is_leaf: bool,     // = false;
     pub next: i32,         // = -1;
+    pub margin: f32,       // = 0; запас "толстого" листа, задаётся в create_proxy
 }
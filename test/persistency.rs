@@ -0,0 +1,308 @@
+// Сохранение и загрузка мира в JSON. Формат совместим с save.json:
+// { "entities": [ { "pos": [..], "size": [..], "type": "static", ... } ], "player": { "hp": .., "pos": [..] } }
+// id необязателен: сохранённые id восстанавливаются как есть, сущности без id получают новые.
+// category/mask необязательны — если их нет, они выводятся из "type" по слоям из "layers"
+// (category = бит слоя с этим именем, mask = слои, которые он видит по матрице).
+// Без "layers" используется раскладка по умолчанию: static, trigger, player.
+use crate::Vec3;
+use crate::entity::{Activity, BodyKind, DamageZone, Lifetime, RigidBody};
+use crate::layers::{CollisionLayers, SavedLayers};
+use crate::world::World;
+use glam::{DVec3, Quat};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const PLAYER_SIZE: Vec3 = Vec3::new(0.6, 1.8, 0.6);
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layers: Option<SavedLayers>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<DVec3>, // плавающее начало координат; pos сущностей отсчитываются от него
+    pub entities: Vec<SavedEntity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player: Option<SavedPlayer>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedEntity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    pub pos: Vec3,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub world_pos: Option<DVec3>, // точная позиция в мире, если она задана (World::create_entity_at)
+    pub size: Vec3,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Quat>, // кватернион [x, y, z, w], нет — бокс выровнен по осям
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifetime: Option<f32>, // оставшееся время жизни, секунды
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hp: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub damage: Option<f32>, // урон в секунду, если сущность — зона урона
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_trigger: Option<String>, // имена обработчиков, см. events.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_interact: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_expire: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<i32>, // id родителя; pos сохраняется мировой, смещение восстанавливается из неё
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>, // static / dynamic / kinematic; нет — static для типа "static", иначе dynamic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity: Option<String>, // sleeping / disabled; нет — awake
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass: Option<f32>, // есть — сущность твёрдое тело (RigidBody), 0 — бесконечная масса
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity: Option<Vec3>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restitution: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub friction: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub hp: f32,
+    pub pos: Vec3,
+}
+
+fn layers_for_type(layers: &CollisionLayers, kind: &str) -> Option<(i32, i32)> {
+    layers.bit(kind).map(|bit| (bit, layers.row(bit)))
+}
+
+// Имя младшего именованного слоя сущности
+fn type_for_category(layers: &CollisionLayers, category: i32) -> String {
+    layers.names().iter()
+        .find(|name| category & layers.bit(name).unwrap() != 0)
+        .cloned()
+        .unwrap_or("custom".to_string())
+}
+
+pub fn to_save(world: &World) -> SaveFile {
+    let entities = world.registry.sorted_ids().into_iter()
+        .map(|id| {
+            let e = &world.registry[id];
+            let rigid = world.component::<RigidBody>(id);
+            SavedEntity {
+                id: Some(id),
+                pos: e.pos,
+                world_pos: e.world_pos,
+                size: e.size,
+                rotation: e.rotation,
+                kind: type_for_category(&world.layers, e.category),
+                category: Some(e.category),
+                mask: Some(e.mask),
+                lifetime: world.component::<Lifetime>(id).map(|l| l.remaining),
+                hp: Some(e.gameplay.health),
+                damage: world.component::<DamageZone>(id).map(|z| z.per_second),
+                on_trigger: e.on_trigger.clone(),
+                on_interact: e.on_interact.clone(),
+                on_expire: e.on_expire.clone(),
+                parent: world.parent_of(id),
+                body: Some(e.body.name().to_string()),
+                activity: (e.activity != Activity::Awake).then(|| e.activity.name().to_string()),
+                mass: rigid.map(|rb| rb.mass()),
+                velocity: rigid.map(|rb| rb.velocity),
+                restitution: rigid.map(|rb| rb.restitution),
+                friction: rigid.map(|rb| rb.friction),
+            }
+        })
+        .collect();
+    let origin = (world.origin != DVec3::ZERO).then_some(world.origin);
+    SaveFile { layers: Some(world.layers.to_saved()), origin, entities, player: None }
+}
+
+pub fn from_save(save: &SaveFile) -> Result<World, String> {
+    let mut world = World::new();
+    if let Some(layers) = &save.layers {
+        world.layers = CollisionLayers::from_saved(layers)?;
+    }
+    world.origin = save.origin.unwrap_or(DVec3::ZERO);
+    // Сначала сущности с id, чтобы новые id не заняли их слоты
+    let mut order: Vec<usize> = (0..save.entities.len()).collect();
+    order.sort_by_key(|&i| save.entities[i].id.is_none());
+    let mut ids = vec![-1; save.entities.len()];
+    let mut activities = Vec::new();
+    for i in order {
+        let s = &save.entities[i];
+        let (cat, mask) = match (s.category, s.mask) {
+            (Some(c), Some(m)) => (c, m),
+            (c, m) => {
+                let (tc, tm) = layers_for_type(&world.layers, &s.kind)
+                    .ok_or(format!("entity {}: unknown type \"{}\" and no category/mask", i, s.kind))?;
+                (c.unwrap_or(tc), m.unwrap_or(tm))
+            }
+        };
+        let id = match s.id {
+            Some(id) => {
+                world.create_entity_with_id(id, s.pos, s.size, cat, mask).map_err(|e| format!("entity {}: {}", i, e))?;
+                id
+            }
+            None => world.create_entity(s.pos, s.size, cat, mask),
+        };
+        ids[i] = id;
+        let body = match &s.body {
+            Some(name) => BodyKind::parse(name).ok_or(format!("entity {}: unknown body \"{}\"", i, name))?,
+            None if s.kind == "static" => BodyKind::Static,
+            None => BodyKind::Dynamic,
+        };
+        world.set_body(id, body);
+        if let Some(name) = &s.activity {
+            let activity = Activity::parse(name).ok_or(format!("entity {}: unknown activity \"{}\"", i, name))?;
+            activities.push((id, activity));
+        }
+        if s.rotation.is_some() { world.set_rotation(id, s.rotation); }
+        if let Some(seconds) = s.lifetime {
+            world.insert_component(id, Lifetime::new(seconds))?;
+        }
+        if let Some(per_second) = s.damage {
+            world.insert_component(id, DamageZone { per_second })?;
+        }
+        if let Some(mass) = s.mass {
+            let mut rb = RigidBody::new(mass);
+            rb.velocity = s.velocity.unwrap_or(Vec3::ZERO);
+            rb.restitution = s.restitution.unwrap_or(rb.restitution);
+            rb.friction = s.friction.unwrap_or(rb.friction);
+            world.insert_component(id, rb)?;
+        }
+        let e = &mut world.registry[id];
+        e.world_pos = s.world_pos;
+        if let Some(hp) = s.hp { e.gameplay.health = hp; }
+        e.on_trigger = s.on_trigger.clone();
+        e.on_interact = s.on_interact.clone();
+        e.on_expire = s.on_expire.clone();
+    }
+    // Иерархия — когда все сущности уже на месте, родитель может идти в файле после ребёнка
+    for (i, s) in save.entities.iter().enumerate() {
+        if let Some(parent) = s.parent {
+            world.set_parent(ids[i], parent).map_err(|e| format!("entity {}: parent: {}", i, e))?;
+        }
+    }
+    if let Some(p) = &save.player {
+        let (cat, mask) = layers_for_type(&world.layers, "player").ok_or("player: no \"player\" layer")?;
+        let id = world.create_entity(p.pos, PLAYER_SIZE, cat, mask);
+        world.registry[id].gameplay.health = p.hp;
+    }
+    world.flush();
+    // Активность — последней: поворот и прочие set_* будят сущность
+    for (id, activity) in activities { world.set_activity(id, activity); }
+    // Уровень расставлен — статическое дерево строится один раз по SAH
+    world.build_static();
+    // Загрузка — не игровые события: Spawned от восстановления мира никому не нужны,
+    // и восстановленные сущности не считаются сдвинутыми — иначе первый step разбудит спящих
+    world.events.clear();
+    world.recently_moved.clear();
+    Ok(world)
+}
+
+pub fn save_world(world: &World, path: &Path) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&to_save(world)).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn load_world(path: &Path) -> Result<World, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let save: SaveFile = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    from_save(&save)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SaveFile, from_save, to_save};
+    use crate::Vec3;
+    use crate::entity::{Activity, BodyKind, DamageZone, Lifetime, RigidBody};
+    use crate::world::World;
+    use glam::{DVec3, Quat};
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut world = World::new();
+        let lava = world.layers.add("lava").unwrap();
+        let player = world.layers.bit("player").unwrap();
+        world.layers.set_sees(lava, player, true);
+        world.shift_origin(Vec3::new(1000.0, 0.0, 0.0));
+
+        let gone = world.create_entity(Vec3::ZERO, Vec3::ONE, player, player);
+        world.mark_for_deletion(gone);
+        world.cleanup();
+        // Слот gone занят заново — у id второе поколение
+        let crate_id = world.create_entity(Vec3::new(0.0, 2.0, 0.0), Vec3::ONE, player, player);
+        assert_ne!(crate_id, gone);
+        let mut rb = RigidBody::new(4.0);
+        rb.velocity = Vec3::new(1.0, -2.0, 0.5);
+        rb.restitution = 0.3;
+        rb.friction = 0.9;
+        world.insert_component(crate_id, rb).unwrap();
+        world.set_rotation(crate_id, Some(Quat::from_rotation_y(0.7)));
+
+        let pool = world.create_entity_at(DVec3::new(1000.125, 0.0, 4.0), Vec3::new(4.0, 0.2, 4.0), lava, player);
+        world.insert_component(pool, DamageZone { per_second: 12.5 }).unwrap();
+        world.insert_component(pool, Lifetime::new(3.0)).unwrap();
+        world.set_body(pool, BodyKind::Kinematic);
+        let lid = world.create_entity(Vec3::new(0.0, 1.0, 4.0), Vec3::ONE, lava, 0);
+        world.set_parent(lid, pool).unwrap();
+        world.registry[lid].gameplay.health = 7.0;
+        world.registry[lid].on_interact = Some("open".to_string());
+        world.registry[pool].on_expire = Some("splash".to_string());
+        world.set_activity(lid, Activity::Disabled);
+        let wall = world.create_entity(Vec3::new(0.0, 0.0, -5.0), Vec3::new(10.0, 3.0, 1.0), 1, 0);
+        world.set_body(wall, BodyKind::Static);
+        world.set_activity(wall, Activity::Sleeping);
+        world.flush();
+
+        // Через текст — как save_world / load_world
+        let json = serde_json::to_string(&to_save(&world)).unwrap();
+        let loaded = from_save(&serde_json::from_str::<SaveFile>(&json).unwrap()).unwrap();
+
+        assert_eq!(loaded.registry.sorted_ids(), world.registry.sorted_ids());
+        assert_eq!(loaded.origin, world.origin);
+        assert_eq!(loaded.layers.names(), world.layers.names());
+        assert!(loaded.layers.sees(lava, player) && !loaded.layers.sees(player, lava));
+        for id in world.registry.sorted_ids() {
+            let (a, b) = (&world.registry[id], &loaded.registry[id]);
+            assert_eq!((a.pos, a.size, a.rotation, a.world_pos), (b.pos, b.size, b.rotation, b.world_pos), "entity {}", id);
+            assert_eq!((a.category, a.mask, a.body, a.activity), (b.category, b.mask, b.body, b.activity), "entity {}", id);
+            assert_eq!((a.gameplay.health, &a.on_interact, &a.on_trigger), (b.gameplay.health, &b.on_interact, &b.on_trigger));
+            assert_eq!(a.on_expire, b.on_expire, "entity {}", id);
+            assert_eq!(world.parent_of(id), loaded.parent_of(id), "entity {}", id);
+            assert_eq!(world.children(id), loaded.children(id), "entity {}", id);
+            assert_eq!(b.local_pos, a.local_pos, "entity {}", id);
+        }
+        let rb = loaded.component::<RigidBody>(crate_id).unwrap();
+        assert_eq!((rb.mass(), rb.velocity, rb.restitution, rb.friction), (4.0, Vec3::new(1.0, -2.0, 0.5), 0.3, 0.9));
+        assert_eq!(loaded.component::<DamageZone>(pool).map(|z| z.per_second), Some(12.5));
+        assert_eq!(loaded.component::<Lifetime>(pool).map(|l| l.remaining), Some(3.0));
+        assert!(loaded.component::<Lifetime>(crate_id).is_none() && loaded.component::<RigidBody>(pool).is_none());
+        assert_eq!(loaded.tree(BodyKind::Static).proxy_count, 1);
+
+        // Новые сущности не задевают восстановленные id
+        let mut loaded = loaded;
+        let fresh = loaded.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1);
+        assert!(!world.registry.contains(fresh) && loaded.registry.len() == world.registry.len() + 1);
+    }
+
+    #[test]
+    fn sleeping_entities_stay_asleep_after_load() {
+        let mut world = World::new();
+        let a = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1);
+        let b = world.create_entity(Vec3::new(0.8, 0.0, 0.0), Vec3::ONE, 1, 1);
+        world.set_rotation(b, Some(Quat::from_rotation_y(0.3)));
+        for id in [a, b] { world.set_activity(id, Activity::Sleeping); }
+
+        let mut loaded = from_save(&to_save(&world)).unwrap();
+        assert!(loaded.recently_moved.is_empty());
+        loaded.step(0.1);
+        for id in [a, b] {
+            assert_eq!(loaded.registry[id].activity, Activity::Sleeping, "entity {} woke up after load", id);
+        }
+    }
+}
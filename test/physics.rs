@@ -0,0 +1,218 @@
+// Простейшая физика твёрдых тел поверх World: гравитация, скорости и выталкивание по AABB-контактам
+// (contacts.rs) последовательными импульсами с упругостью и трением. Вращения нет — только линейное
+// движение. step_physics режет dt на фиксированные шаги physics_dt; остаток копится до следующего вызова,
+// а отставание сверх MAX_SUBSTEPS выбрасывается и учитывается в World::physics_dropped.
+// Сдвиги идут через update_positions, так что прокси обновляет обычная логика толстых листьев, а
+// сдвинутые тела будят соседей на ближайшем step.
+use crate::Vec3;
+use crate::entity::{Activity, BodyKind, RigidBody};
+use crate::world::World;
+use std::collections::HashMap;
+
+const MAX_SUBSTEPS: u32 = 8;      // больше за вызов не догоняем — иначе тяжёлый кадр тянет за собой следующие
+const ITERATIONS: usize = 8;      // проходов по контактам за шаг
+const SLOP: f32 = 0.01;           // допустимое проникновение: без него лежащий ящик дрожит
+const CORRECTION: f32 = 0.8;      // доля оставшегося проникновения, выталкиваемая за шаг сдвигом, без скорости
+const BOUNCE_THRESHOLD: f32 = 1.0; // медленнее этого удары не отскакивают
+
+// Контакт в решателе: индексы тел в bodies (None — неподвижная сторона) и накопленные импульсы
+struct Constraint {
+    a: Option<usize>,
+    b: Option<usize>,
+    normal: Vec3,
+    inv_a: f32,
+    inv_b: f32,
+    depth: f32,
+    bias: f32, // целевая скорость расхождения вдоль нормали — только для отскока
+    friction: f32,
+    normal_impulse: f32,
+    tangent_impulse: Vec3,
+}
+
+#[rustfmt::skip]
+impl World {
+    // Возвращает число сделанных фиксированных шагов
+    pub fn step_physics(&mut self, dt: f32) -> u32 {
+        if self.physics_dt <= 0.0 { return 0; }
+        self.flush();
+        self.physics_time += dt;
+        let mut steps = 0;
+        while self.physics_time >= self.physics_dt && steps < MAX_SUBSTEPS {
+            self.physics_substep(self.physics_dt);
+            self.physics_time -= self.physics_dt;
+            steps += 1;
+        }
+        // Догнать не успели: в запасе остаётся не больше одного шага, остальное — в physics_dropped,
+        // чтобы замедление было видно в stats, а не пряталось
+        let excess = self.physics_time - self.physics_dt;
+        if excess > 0.0 {
+            self.physics_dropped += excess as f64;
+            self.physics_time = self.physics_dt;
+        }
+        steps
+    }
+    fn simulated(&self, id: i32) -> bool {
+        let e = &self.registry[id];
+        e.body == BodyKind::Dynamic && e.activity == Activity::Awake && e.parent == -1 && !e.gameplay.is_dirty
+    }
+    fn physics_substep(&mut self, h: f32) {
+        if self.components.store::<RigidBody>().is_none_or(|s| s.is_empty()) { return; }
+        // Спящий ящик, которого коснулось движущееся тело, просыпается сразу, а не на следующем step
+        let contacts = self.contacts();
+        let mut woken = Vec::new();
+        for c in &contacts {
+            for (id, other) in [(c.a, c.b), (c.b, c.a)] {
+                let sleeping = self.registry[id].activity == Activity::Sleeping && self.components.has::<RigidBody>(id);
+                if sleeping && self.simulated(other) { woken.push(id); }
+            }
+        }
+        for id in woken { self.wake(id); }
+
+        // (id, скорость, 1 / масса) симулируемых тел
+        let mut bodies: Vec<(i32, Vec3, f32)> = self.components.iter::<RigidBody>()
+            .filter(|&(id, _)| self.simulated(id))
+            .map(|(id, rb)| (id, rb.velocity, rb.inv_mass))
+            .collect();
+        let index: HashMap<i32, usize> = bodies.iter().enumerate().map(|(i, b)| (b.0, i)).collect();
+        for b in bodies.iter_mut() {
+            if b.2 > 0.0 { b.1 += self.gravity * h; }
+        }
+
+        let material = |id: i32| self.components.get::<RigidBody>(id).map_or((0.0, 0.5), |rb| (rb.restitution, rb.friction));
+        let mut constraints = Vec::new();
+        for c in &contacts {
+            let (a, b) = (index.get(&c.a).copied(), index.get(&c.b).copied());
+            let (inv_a, inv_b) = (a.map_or(0.0, |i| bodies[i].2), b.map_or(0.0, |i| bodies[i].2));
+            if inv_a + inv_b == 0.0 { continue; }
+            let normal = c.manifold.normal;
+            let (va, vb) = (a.map_or(Vec3::ZERO, |i| bodies[i].1), b.map_or(Vec3::ZERO, |i| bodies[i].1));
+            let approach = (vb - va).dot(normal);
+            let ((ra, fa), (rb, fb)) = (material(c.a), material(c.b));
+            let bias = if approach < -BOUNCE_THRESHOLD { -ra.max(rb) * approach } else { 0.0 };
+            constraints.push(Constraint {
+                a, b, normal, inv_a, inv_b, bias,
+                depth: c.manifold.depth,
+                friction: (fa * fb).sqrt(),
+                normal_impulse: 0.0,
+                tangent_impulse: Vec3::ZERO,
+            });
+        }
+
+        for _ in 0..ITERATIONS {
+            for c in constraints.iter_mut() {
+                let k = c.inv_a + c.inv_b;
+                let velocity = |bodies: &[(i32, Vec3, f32)]| {
+                    c.b.map_or(Vec3::ZERO, |i| bodies[i].1) - c.a.map_or(Vec3::ZERO, |i| bodies[i].1)
+                };
+                // Нормаль: накопленный импульс только расталкивает
+                let vn = velocity(&bodies).dot(c.normal);
+                let total = (c.normal_impulse + (c.bias - vn) / k).max(0.0);
+                let impulse = c.normal * (total - c.normal_impulse);
+                c.normal_impulse = total;
+                if let Some(i) = c.a { bodies[i].1 -= impulse * c.inv_a; }
+                if let Some(i) = c.b { bodies[i].1 += impulse * c.inv_b; }
+
+                // Трение гасит скольжение, но не сильнее friction * нормальный импульс
+                let v = velocity(&bodies);
+                let vt = v - c.normal * v.dot(c.normal);
+                let total = (c.tangent_impulse - vt / k).clamp_length_max(c.friction * c.normal_impulse);
+                let impulse = total - c.tangent_impulse;
+                c.tangent_impulse = total;
+                if let Some(i) = c.a { bodies[i].1 -= impulse * c.inv_a; }
+                if let Some(i) = c.b { bodies[i].1 += impulse * c.inv_b; }
+            }
+        }
+
+        // Проникновение, оставшееся после этого шага, выталкиваем сдвигом: через скорость оно
+        // добавляло бы энергии и неупругий удар превращался бы в отскок
+        let mut deltas: Vec<Vec3> = bodies.iter().map(|b| b.1 * h).collect();
+        for c in &constraints {
+            let closing = (c.b.map_or(Vec3::ZERO, |i| bodies[i].1) - c.a.map_or(Vec3::ZERO, |i| bodies[i].1)).dot(c.normal) * h;
+            let push = c.normal * (CORRECTION * (c.depth - closing - SLOP).max(0.0) / (c.inv_a + c.inv_b));
+            if let Some(i) = c.a { deltas[i] -= push * c.inv_a; }
+            if let Some(i) = c.b { deltas[i] += push * c.inv_b; }
+        }
+        // Покоящееся тело не трогаем — иначе оно будет вечно "двигаться" на месте и не уснёт
+        let mut moves = Vec::new();
+        for (&(id, velocity, _), delta) in bodies.iter().zip(deltas) {
+            self.components.get_mut::<RigidBody>(id).unwrap().velocity = velocity;
+            if delta.length_squared() > 1e-12 { moves.push((id, self.registry[id].pos + delta)); }
+        }
+        if !moves.is_empty() { self.update_positions(&moves); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Vec3;
+    use crate::entity::{Activity, BodyKind, RigidBody};
+    use crate::world::World;
+
+    #[test]
+    fn rigid_bodies_settle_and_collide() {
+        let mut world = World::new();
+        let (cat, mask) = (1, 1);
+        world.layers.set_collides(cat, cat, true);
+        let floor = world.create_entity(Vec3::new(0.0, -0.5, 0.0), Vec3::new(40.0, 1.0, 40.0), cat, mask);
+        world.set_body(floor, BodyKind::Static);
+
+        // Ящик падает на пол, лежит на нём с проникновением не больше допуска и засыпает
+        let crate_id = world.create_entity(Vec3::new(0.0, 3.0, 0.0), Vec3::ONE, cat, mask);
+        world.insert_component(crate_id, RigidBody::new(2.0)).unwrap();
+        for _ in 0..120 {
+            world.step_physics(1.0 / 30.0);
+            world.step(1.0 / 30.0);
+        }
+        let y = world.registry[crate_id].pos.y;
+        assert!((y - 0.5).abs() < 0.02, "crate rests at {}", y);
+        assert_eq!(world.registry[crate_id].activity, Activity::Sleeping);
+
+        // Упругий лобовой удар равных масс: скорости меняются местами, импульс сохраняется
+        let a = world.create_entity(Vec3::new(-3.0, 5.0, 0.0), Vec3::ONE, cat, mask);
+        let b = world.create_entity(Vec3::new(3.0, 5.0, 0.0), Vec3::ONE, cat, mask);
+        for (id, vx) in [(a, 4.0), (b, -4.0)] {
+            let mut rb = RigidBody::new(1.0);
+            rb.velocity = Vec3::new(vx, 0.0, 0.0);
+            rb.restitution = 1.0;
+            world.insert_component(id, rb).unwrap();
+        }
+        world.gravity = Vec3::ZERO;
+        for _ in 0..60 { world.step_physics(1.0 / 60.0); }
+        let (va, vb) = (world.component::<RigidBody>(a).unwrap().velocity.x, world.component::<RigidBody>(b).unwrap().velocity.x);
+        assert!(va < -3.5 && vb > 3.5, "velocities after bounce {} {}", va, vb);
+        assert!((va + vb).abs() < 1e-3, "momentum {}", va + vb);
+        assert!(world.registry[a].pos.x < world.registry[b].pos.x - 0.9, "boxes passed through each other");
+
+        // Неупругий удар лёгкого о тяжёлый: общая скорость по закону сохранения импульса
+        let light = world.create_entity(Vec3::new(-3.0, 10.0, 0.0), Vec3::ONE, cat, mask);
+        let heavy = world.create_entity(Vec3::new(0.0, 10.0, 0.0), Vec3::ONE, cat, mask);
+        let mut rb = RigidBody::new(1.0);
+        rb.velocity = Vec3::new(6.0, 0.0, 0.0);
+        world.insert_component(light, rb).unwrap();
+        world.insert_component(heavy, RigidBody::new(3.0)).unwrap();
+        for _ in 0..60 { world.step_physics(1.0 / 60.0); }
+        for id in [light, heavy] {
+            let v = world.component::<RigidBody>(id).unwrap().velocity.x;
+            assert!((v - 1.5).abs() < 0.05, "entity {} moves at {} after inelastic hit", id, v);
+        }
+    }
+
+    #[test]
+    fn long_frames_drop_time_beyond_the_substep_cap() {
+        let mut world = World::new();
+        world.physics_dt = 0.25;
+        let id = world.create_entity(Vec3::new(0.0, 100.0, 0.0), Vec3::ONE, 1, 0);
+        world.insert_component(id, RigidBody::new(1.0)).unwrap();
+
+        // Остаток меньше шага копится как есть
+        assert_eq!(world.step_physics(0.6), 2);
+        assert!((world.physics_time - 0.1).abs() < 1e-6 && world.physics_dropped == 0.0);
+
+        // 0.1 + 3.0 — это 12.4 шага: 8 сделано, один остаётся в запасе, 0.85 с выброшено
+        assert_eq!(world.step_physics(3.0), 8);
+        assert_eq!(world.physics_time, 0.25);
+        assert!((world.physics_dropped - 0.85).abs() < 1e-5, "dropped {}", world.physics_dropped);
+        assert_eq!(world.step_physics(0.0), 1);
+        assert_eq!(world.physics_time, 0.0);
+    }
+}
@@ -0,0 +1,461 @@
+// Программный рендер мира для отладки: сущности трассируются лучами через DynamicBvh::ray_cast,
+// боксы узлов дерева, запрос и попадания рисуются поверх каркасом. Результат — PPM или PNG без внешних зависимостей.
+use crate::Aabb;
+use crate::Vec3;
+use crate::ray::Ray;
+use crate::world::World;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub type Rgb = [u8; 3];
+
+const BACKGROUND: Rgb = [24, 26, 32];
+const HIGHLIGHT: Rgb = [255, 64, 255];
+const QUERY_COLOR: Rgb = [255, 255, 255];
+// Цвет сущности — по младшему установленному биту category
+const CATEGORY_PALETTE: [Rgb; 8] = [
+    [150, 150, 150],
+    [220, 80, 60],
+    [80, 200, 90],
+    [70, 120, 230],
+    [230, 200, 60],
+    [60, 210, 210],
+    [240, 140, 40],
+    [170, 90, 220],
+];
+// Цвет узла дерева — по глубине от корня (по кругу)
+const DEPTH_PALETTE: [Rgb; 6] = [
+    [255, 80, 80],
+    [255, 180, 60],
+    [240, 240, 80],
+    [90, 230, 110],
+    [80, 170, 255],
+    [190, 110, 255],
+];
+
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgb>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: vec![BACKGROUND; width * height] }
+    }
+    pub fn set(&mut self, x: i32, y: i32, color: Rgb) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.pixels[y as usize * self.width + x as usize] = color;
+        }
+    }
+    // Брезенхем
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Rgb) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (sx, sy) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+        let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+        loop {
+            self.set(x, y, color);
+            if x == x1 && y == y1 { break; }
+            let e2 = 2 * err;
+            if e2 >= dy { err += dy; x += sx; }
+            if e2 <= dx { err += dx; y += sy; }
+        }
+    }
+    // Формат выбирается по расширению: .png — PNG, иначе бинарный PPM (P6)
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("png")) {
+            self.write_png(&mut out)?;
+        } else {
+            self.write_ppm(&mut out)?;
+        }
+        out.flush()
+    }
+    pub fn write_ppm(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for p in &self.pixels { out.write_all(p)?; }
+        Ok(())
+    }
+    // PNG без сжатия: zlib-поток из stored-блоков deflate
+    pub fn write_png(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 бит, RGB, deflate, без фильтров, без interlace
+        write_chunk(out, b"IHDR", &ihdr)?;
+
+        // По строкам через индексы, а не chunks(width): при нулевой ширине строки всё равно есть
+        let mut raw = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        for y in 0..self.height {
+            raw.push(0); // фильтр None
+            for p in &self.pixels[y * self.width..(y + 1) * self.width] { raw.extend_from_slice(p); }
+        }
+        let mut zlib = vec![0x78, 0x01];
+        let blocks: Vec<&[u8]> = raw.chunks(65535).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            zlib.push(if i + 1 == blocks.len() { 1 } else { 0 });
+            let len = block.len() as u16;
+            zlib.extend_from_slice(&len.to_le_bytes());
+            zlib.extend_from_slice(&(!len).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        if blocks.is_empty() { zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]); }
+        zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+        write_chunk(out, b"IDAT", &zlib)?;
+        write_chunk(out, b"IEND", &[])
+    }
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let mut crc = crc32_update(0xffff_ffff, kind);
+    crc = crc32_update(crc, data);
+    out.write_all(&(!crc).to_be_bytes())
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+pub struct Camera {
+    pub eye: Vec3,
+    pub forward: Vec3,
+    pub right: Vec3,
+    pub up: Vec3,
+    pub fov_y: f32, // в радианах
+}
+
+impl Camera {
+    pub fn look_at(eye: Vec3, target: Vec3, fov_y_deg: f32) -> Self {
+        let forward = (target - eye).normalize();
+        // Если смотрим строго вертикально, "верх" берём по оси -Z
+        let world_up = if forward.cross(Vec3::Y).length_squared() < 1e-6 { Vec3::NEG_Z } else { Vec3::Y };
+        let right = forward.cross(world_up).normalize();
+        let up = right.cross(forward);
+        Self { eye, forward, right, up, fov_y: fov_y_deg.to_radians() }
+    }
+    // Вид сверху-сбоку, в кадр помещается весь bounds
+    pub fn overview(bounds: &Aabb) -> Self {
+        let center = (bounds.min + bounds.max) * 0.5;
+        let radius = ((bounds.max - bounds.min).length() * 0.5).max(1.0);
+        let dir = Vec3::new(0.6, 1.0, 0.8).normalize();
+        Self::look_at(center + dir * radius * 2.2, center, 50.0)
+    }
+    // Пустой кадр считается 1x1, чтобы не делить на ноль
+    fn ray(&self, px: f32, py: f32, width: usize, height: usize) -> Ray {
+        let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+        let aspect = width / height;
+        let h = (self.fov_y * 0.5).tan();
+        let sx = (2.0 * (px + 0.5) / width - 1.0) * h * aspect;
+        let sy = (1.0 - 2.0 * (py + 0.5) / height) * h;
+        Ray::new(self.eye, (self.forward + self.right * sx + self.up * sy).normalize())
+    }
+    // Проекция точки на экран; None — точка за камерой или кадр пустой
+    fn project(&self, p: Vec3, width: usize, height: usize) -> Option<(i32, i32)> {
+        if width == 0 || height == 0 { return None; }
+        let d = p - self.eye;
+        let z = d.dot(self.forward);
+        if z <= 1e-3 { return None; }
+        let aspect = width as f32 / height as f32;
+        let h = (self.fov_y * 0.5).tan();
+        let sx = d.dot(self.right) / (z * h * aspect);
+        let sy = d.dot(self.up) / (z * h);
+        let x = (sx + 1.0) * 0.5 * width as f32 - 0.5;
+        let y = (1.0 - sy) * 0.5 * height as f32 - 0.5;
+        Some((x.round() as i32, y.round() as i32))
+    }
+}
+
+pub struct RenderOptions {
+    pub width: usize,
+    pub height: usize,
+    pub draw_entities: bool,     // сплошные боксы сущностей, цвет по category
+    pub draw_nodes: bool,        // каркас внутренних узлов BVH, цвет по глубине
+    pub max_node_depth: i32,     // -1 — без ограничения
+    pub draw_leaves: bool,       // каркас "толстых" листьев
+    pub highlight: Vec<i32>,     // id сущностей, подсвечиваемых (например, результат запроса)
+    pub query: Option<Aabb>,     // бокс запроса — белым каркасом
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 480,
+            draw_entities: true,
+            draw_nodes: true,
+            max_node_depth: -1,
+            draw_leaves: false,
+            highlight: Vec::new(),
+            query: None,
+        }
+    }
+}
+
+pub fn category_color(category: i32) -> Rgb {
+    if category == 0 { return CATEGORY_PALETTE[0]; }
+    CATEGORY_PALETTE[1 + category.trailing_zeros() as usize % (CATEGORY_PALETTE.len() - 1)]
+}
+
+// Вход луча в бокс: расстояние и нормаль грани входа
+fn ray_enter(bbox: &Aabb, ray: &Ray) -> Option<(f32, Vec3)> {
+    let t1 = (bbox.min - ray.origin) * ray.inv_dir;
+    let t2 = (bbox.max - ray.origin) * ray.inv_dir;
+    let t_min = t1.min(t2);
+    let t_max = t1.max(t2);
+    let t_enter = t_min.max_element();
+    let t_exit = t_max.min_element();
+    if t_exit < t_enter || t_exit <= 0.0 { return None; }
+
+    let axis = if t_enter == t_min.x { 0 } else if t_enter == t_min.y { 1 } else { 2 };
+    let mut normal = Vec3::ZERO;
+    normal[axis] = -ray.direction[axis].signum();
+    Some((t_enter.max(0.0), normal))
+}
+
+fn shade(color: Rgb, normal: Vec3) -> Rgb {
+    let light = Vec3::new(0.4, 1.0, 0.6).normalize();
+    let k = 0.35 + 0.65 * normal.dot(light).max(0.0);
+    [(color[0] as f32 * k) as u8, (color[1] as f32 * k) as u8, (color[2] as f32 * k) as u8]
+}
+
+fn draw_box(fb: &mut Framebuffer, camera: &Camera, bbox: &Aabb, color: Rgb) {
+    let corners = std::array::from_fn(|i| Vec3::new(
+        if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+        if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+        if i & 4 == 0 { bbox.min.z } else { bbox.max.z },
+    ));
+    draw_edges(fb, camera, &corners, color);
+}
+
+// Рёбра бокса по углам в порядке Obb::corners: соседние углы отличаются одним битом номера
+fn draw_edges(fb: &mut Framebuffer, camera: &Camera, corners: &[Vec3; 8], color: Rgb) {
+    for a in 0..8 {
+        for bit in [1, 2, 4] {
+            let b = a | bit;
+            if b == a { continue; }
+            let (Some(p0), Some(p1)) = (
+                camera.project(corners[a], fb.width, fb.height),
+                camera.project(corners[b], fb.width, fb.height),
+            ) else { continue };
+            fb.line(p0.0, p0.1, p1.0, p1.1, color);
+        }
+    }
+}
+
+pub fn render(world: &World, camera: &Camera, options: &RenderOptions) -> Framebuffer {
+    let mut fb = Framebuffer::new(options.width, options.height);
+
+    if options.draw_entities {
+        for y in 0..options.height {
+            for x in 0..options.width {
+                let ray = camera.ray(x as f32, y as f32, options.width, options.height);
+                // Дерево отдаёт кандидатов по "толстым" листьям, ближайшее попадание ищем по точным боксам
+                let mut nearest: Option<(f32, Vec3, i32)> = None;
+                for id in world.trees().into_iter().flat_map(|(_, tree)| tree.ray_cast(&ray)) {
+                    let Some(entity) = world.registry.get(id) else { continue };
+                    // Повёрнутый бокс: пересекаем в его координатах и поворачиваем нормаль обратно
+                    let hit = match entity.rotation {
+                        None => ray_enter(&entity.get_aabb(), &ray),
+                        Some(q) => {
+                            let obb = entity.get_obb();
+                            ray_enter(&Aabb::new(-obb.half, obb.half), &obb.local_ray(&ray)).map(|(t, n)| (t, q * n))
+                        }
+                    };
+                    if let Some((t, n)) = hit
+                        && nearest.is_none_or(|(best, _, _)| t < best) {
+                        nearest = Some((t, n, id));
+                    }
+                }
+                if let Some((_, normal, id)) = nearest {
+                    let base = if options.highlight.contains(&id) {
+                        HIGHLIGHT
+                    } else {
+                        category_color(world.registry[id].category)
+                    };
+                    fb.set(x as i32, y as i32, shade(base, normal));
+                }
+            }
+        }
+    }
+
+    // Оба дерева — статика и подвижные — одной палитрой по глубине
+    for (_, tree) in world.trees() {
+        if !(options.draw_nodes || options.draw_leaves) || tree.root == -1 { continue; }
+        let mut stack = vec![(tree.root, 0)];
+        while let Some((idx, depth)) = stack.pop() {
+            let node = &tree.nodes[idx as usize];
+            if node.is_leaf {
+                if options.draw_leaves { draw_box(&mut fb, camera, &node.bbox, [120, 120, 120]); }
+                continue;
+            }
+            if options.draw_nodes && (options.max_node_depth < 0 || depth <= options.max_node_depth) {
+                draw_box(&mut fb, camera, &node.bbox, DEPTH_PALETTE[depth as usize % DEPTH_PALETTE.len()]);
+            }
+            stack.push((node.child1, depth + 1));
+            stack.push((node.child2, depth + 1));
+        }
+    }
+
+    for id in &options.highlight {
+        if let Some(entity) = world.registry.get(*id) {
+            draw_edges(&mut fb, camera, &entity.get_obb().corners(), HIGHLIGHT);
+        }
+    }
+    if let Some(query) = &options.query {
+        draw_box(&mut fb, camera, query, QUERY_COLOR);
+    }
+    fb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BACKGROUND, Camera, Framebuffer, HIGHLIGHT, RenderOptions, adler32, category_color, crc32_update, render};
+    use crate::Vec3;
+    use crate::world::World;
+    use glam::Quat;
+
+    fn gradient(width: usize, height: usize) -> Framebuffer {
+        let mut fb = Framebuffer::new(width, height);
+        for (i, p) in fb.pixels.iter_mut().enumerate() {
+            *p = [i as u8, (i >> 8) as u8, (i % 7) as u8];
+        }
+        fb
+    }
+
+    // Разбирает PNG обратно: проверяет сигнатуру и CRC каждого чанка, возвращает (тип, данные)
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut out = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+            let data = rest[8..8 + len].to_vec();
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+            assert_eq!(crc, !crc32_update(crc32_update(0xffff_ffff, &kind), &data), "bad crc in {:?}", kind);
+            out.push((kind, data));
+            rest = &rest[12 + len..];
+        }
+        out
+    }
+
+    #[test]
+    fn ppm_is_header_plus_raw_pixels() {
+        let fb = gradient(3, 2);
+        let mut out = Vec::new();
+        fb.write_ppm(&mut out).unwrap();
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(out[header.len()..], *fb.pixels.concat());
+    }
+
+    #[test]
+    fn png_round_trips_through_stored_deflate() {
+        // Больше 64 КиБ сырых данных — несколько stored-блоков
+        let fb = gradient(200, 120);
+        let mut png = Vec::new();
+        fb.write_png(&mut png).unwrap();
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|c| &c.0).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 200, 0, 0, 0, 120, 8, 2, 0, 0, 0]);
+
+        let zlib = &chunks[1].1;
+        assert_eq!(&zlib[..2], [0x78, 0x01]);
+        let (mut raw, mut at, mut last) = (Vec::new(), 2, false);
+        while !last {
+            last = zlib[at] == 1;
+            let len = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]);
+            assert_eq!(!len, u16::from_le_bytes([zlib[at + 3], zlib[at + 4]]));
+            raw.extend_from_slice(&zlib[at + 5..at + 5 + len as usize]);
+            at += 5 + len as usize;
+        }
+        assert_eq!(zlib[at..], adler32(&raw).to_be_bytes());
+        let expected: Vec<u8> = fb.pixels.chunks(200).flat_map(|row| std::iter::once(0).chain(row.concat())).collect();
+        assert_eq!(raw, expected);
+    }
+
+    #[test]
+    fn empty_images_are_written_and_rendered() {
+        for (w, h) in [(0, 0), (0, 3), (4, 0)] {
+            let mut png = Vec::new();
+            Framebuffer::new(w, h).write_png(&mut png).unwrap();
+            let chunks = chunks(&png);
+            assert_eq!(chunks[0].1[..8], [(w as u32).to_be_bytes(), (h as u32).to_be_bytes()].concat());
+        }
+        let mut world = World::new();
+        let id = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 0);
+        let camera = Camera::look_at(Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO, 40.0);
+        assert_eq!(camera.project(Vec3::ZERO, 0, 0), None);
+        let fb = render(&world, &camera, &RenderOptions { width: 0, height: 0, highlight: vec![id], ..Default::default() });
+        assert!(fb.pixels.is_empty());
+    }
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(!crc32_update(0xffff_ffff, b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn entity_in_view_is_drawn_in_its_category_color() {
+        let mut world = World::new();
+        let id = world.create_entity(Vec3::ZERO, Vec3::splat(2.0), 2, 0);
+        let camera = Camera::look_at(Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO, 40.0);
+        let options = RenderOptions { width: 32, height: 24, draw_nodes: false, ..Default::default() };
+        let fb = render(&world, &camera, &options);
+
+        // Грань смотрит на камеру (+z): цвет слоя с затенением, по краям кадра — фон
+        let center = fb.pixels[12 * 32 + 16];
+        let base = category_color(2);
+        assert_ne!(center, BACKGROUND);
+        assert!(center.iter().zip(base).all(|(&c, b)| c <= b && c > 0), "center {:?} vs {:?}", center, base);
+        assert_eq!(fb.pixels[0], BACKGROUND);
+
+        let highlighted = render(&world, &camera, &RenderOptions { highlight: vec![id], ..options });
+        assert_ne!(highlighted.pixels[12 * 32 + 16], center);
+    }
+
+    #[test]
+    fn highlight_follows_the_rotated_box() {
+        let mut world = World::new();
+        let id = world.create_entity(Vec3::ZERO, Vec3::splat(2.0), 1, 0);
+        world.set_rotation(id, Some(Quat::from_rotation_y(std::f32::consts::FRAC_PI_4)));
+        let camera = Camera::look_at(Vec3::new(0.0, 10.0, 0.01), Vec3::ZERO, 40.0);
+        let options = RenderOptions { width: 200, height: 200, draw_entities: false, draw_nodes: false, highlight: vec![id], ..Default::default() };
+        let fb = render(&world, &camera, &options);
+        // Подсвечен ли пиксель точки или соседний: концы отрезков округляются до пикселя
+        let lit = |p: Vec3| {
+            let (x, y) = camera.project(p, fb.width, fb.height).unwrap();
+            (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| ((y + dy) as usize, (x + dx) as usize)))
+                .any(|(y, x)| fb.pixels[y * fb.width + x] == HIGHLIGHT)
+        };
+
+        // Сверху повёрнутый куб — ромб: его вершины подсвечены, углы плотного AABB — нет
+        let r = 2f32.sqrt();
+        assert!(lit(Vec3::new(r, 1.0, 0.0)) && lit(Vec3::new(0.0, 1.0, -r)));
+        assert!(lit(Vec3::new(r * 0.5, 1.0, r * 0.5)));
+        assert!(!lit(Vec3::new(r, 1.0, r)) && !lit(Vec3::new(-r, 1.0, -r)));
+    }
+}
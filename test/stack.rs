@@ -22,8 +22,4 @@ impl<T> Stack<T> {
     pub fn is_empty(&self) -> bool {
         self.stash.is_empty()
     }
-
-    pub fn clear(&mut self) {
-        self.stash.clear();
-    }
 }
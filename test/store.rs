@@ -0,0 +1,200 @@
+// Хранилище сущностей: slot map с плотным массивом.
+// Сущности лежат подряд в dense (итерация без дыр и без хеширования), sparse[slot] указывает на позицию в dense.
+// Id = (поколение - 1) << SLOT_BITS | (слот + 1): первое поколение даёт привычные id 1, 2, 3...
+// При удалении поколение слота растёт, так что старый id больше ни на что не указывает,
+// даже если слот занят новой сущностью. Id всегда > 0, -1 — "нет сущности".
+use crate::entity::Entity;
+use std::ops::{Index, IndexMut};
+
+const SLOT_BITS: u32 = 20;
+const SLOT_MASK: i32 = (1 << SLOT_BITS) - 1;
+const MAX_GENERATION: i32 = 1 << (31 - SLOT_BITS);
+
+#[derive(Clone, Copy)]
+struct Slot {
+    generation: i32, // 1..=MAX_GENERATION
+    dense: i32,      // индекс в dense или -1, если слот свободен
+}
+
+pub struct EntityStore {
+    dense: Vec<Entity>,
+    sparse: Vec<Slot>,
+    free: Vec<i32>, // свободные слоты, берутся с конца; может держать занятые insert_with_id, см. insert
+}
+
+pub fn slot_of(id: i32) -> i32 {
+    (id & SLOT_MASK) - 1
+}
+
+pub fn generation_of(id: i32) -> i32 {
+    (id >> SLOT_BITS) + 1
+}
+
+fn make_id(slot: i32, generation: i32) -> i32 {
+    (generation - 1) << SLOT_BITS | (slot + 1)
+}
+
+#[rustfmt::skip]
+impl EntityStore {
+    pub fn new() -> Self {
+        Self { dense: Vec::new(), sparse: Vec::new(), free: Vec::new() }
+    }
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+    fn dense_index(&self, id: i32) -> Option<usize> {
+        if id <= 0 || slot_of(id) < 0 { return None; }
+        let slot = self.sparse.get(slot_of(id) as usize)?;
+        if slot.generation != generation_of(id) || slot.dense == -1 { return None; }
+        Some(slot.dense as usize)
+    }
+    pub fn contains(&self, id: i32) -> bool {
+        self.dense_index(id).is_some()
+    }
+    pub fn get(&self, id: i32) -> Option<&Entity> {
+        self.dense_index(id).map(|i| &self.dense[i])
+    }
+    pub fn get_mut(&mut self, id: i32) -> Option<&mut Entity> {
+        self.dense_index(id).map(|i| &mut self.dense[i])
+    }
+    // Выдаёт id и строит сущность уже с ним
+    pub fn insert(&mut self, make: impl FnOnce(i32) -> Entity) -> i32 {
+        let slot = loop {
+            match self.free.pop() {
+                // insert_with_id занимает слоты, не вычищая их из free, — такие просто пропускаем
+                Some(slot) if self.sparse[slot as usize].dense != -1 => continue,
+                Some(slot) => break slot,
+                None => {
+                    assert!(self.sparse.len() < SLOT_MASK as usize, "entity store is full");
+                    self.sparse.push(Slot { generation: 1, dense: -1 });
+                    break self.sparse.len() as i32 - 1;
+                }
+            }
+        };
+        let id = make_id(slot, self.sparse[slot as usize].generation);
+        self.sparse[slot as usize].dense = self.dense.len() as i32;
+        self.dense.push(make(id));
+        id
+    }
+    // Вставка с заранее известным id — при загрузке сохранения
+    pub fn insert_with_id(&mut self, id: i32, make: impl FnOnce(i32) -> Entity) -> Result<(), String> {
+        let (slot, generation) = (slot_of(id), generation_of(id));
+        if id <= 0 || slot < 0 {
+            return Err(format!("bad entity id {}", id));
+        }
+        // Пропущенные слоты — свободные; сам slot в free не попадает
+        while self.sparse.len() <= slot as usize {
+            if self.sparse.len() as i32 != slot { self.free.push(self.sparse.len() as i32); }
+            self.sparse.push(Slot { generation: 1, dense: -1 });
+        }
+        if self.sparse[slot as usize].dense != -1 {
+            return Err(format!("entity id {} collides with an existing entity", id));
+        }
+        self.sparse[slot as usize] = Slot { generation, dense: self.dense.len() as i32 };
+        self.dense.push(make(id));
+        Ok(())
+    }
+    pub fn remove(&mut self, id: i32) -> Option<Entity> {
+        let i = self.dense_index(id)?;
+        let slot = slot_of(id) as usize;
+        // Последняя сущность переезжает на место удалённой
+        let entity = self.dense.swap_remove(i);
+        if let Some(moved) = self.dense.get(i) {
+            self.sparse[slot_of(moved.id) as usize].dense = i as i32;
+        }
+        let s = &mut self.sparse[slot];
+        s.dense = -1;
+        s.generation = if s.generation == MAX_GENERATION { 1 } else { s.generation + 1 };
+        self.free.push(slot as i32);
+        Some(entity)
+    }
+    pub fn clear(&mut self) {
+        self.dense.clear();
+        self.sparse.clear();
+        self.free.clear();
+    }
+    // Плотная итерация, порядок — порядок в dense (меняется при удалениях)
+    pub fn iter(&self) -> std::slice::Iter<'_, Entity> {
+        self.dense.iter()
+    }
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Entity> {
+        self.dense.iter_mut()
+    }
+    pub fn ids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.dense.iter().map(|e| e.id)
+    }
+    // Id по возрастанию — для детерминированного вывода
+    pub fn sorted_ids(&self) -> Vec<i32> {
+        let mut ids: Vec<i32> = self.ids().collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+impl Index<i32> for EntityStore {
+    type Output = Entity;
+    fn index(&self, id: i32) -> &Entity {
+        self.get(id).unwrap_or_else(|| panic!("no entity {}", id))
+    }
+}
+
+impl IndexMut<i32> for EntityStore {
+    fn index_mut(&mut self, id: i32) -> &mut Entity {
+        self.get_mut(id).unwrap_or_else(|| panic!("no entity {}", id))
+    }
+}
+
+impl<'a> IntoIterator for &'a EntityStore {
+    type Item = &'a Entity;
+    type IntoIter = std::slice::Iter<'a, Entity>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.dense.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EntityStore, generation_of, make_id, slot_of};
+    use crate::Vec3;
+    use crate::entity::Entity;
+
+    fn entity(id: i32) -> Entity {
+        Entity::new(id, Vec3::ZERO, Vec3::ONE, 1, 1)
+    }
+
+    #[test]
+    fn removed_ids_stay_dead_after_slot_reuse() {
+        let mut store = EntityStore::new();
+        let a = store.insert(entity);
+        let b = store.insert(entity);
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(store.remove(a).map(|e| e.id), Some(a));
+        let c = store.insert(entity);
+        assert_eq!((slot_of(c), generation_of(c)), (slot_of(a), 2));
+        assert!(!store.contains(a) && store.get(a).is_none() && store.remove(a).is_none());
+        assert_eq!(store[c].id, c);
+        assert_eq!(store.sorted_ids(), [b, c]);
+    }
+
+    #[test]
+    fn ids_from_a_save_leave_gaps_for_new_entities() {
+        let mut store = EntityStore::new();
+        let late = make_id(5, 3);
+        store.insert_with_id(late, entity).unwrap();
+        store.insert_with_id(make_id(2, 1), entity).unwrap();
+        assert!(store.insert_with_id(make_id(2, 4), entity).is_err(), "slot already taken");
+        assert!(store.insert_with_id(0, entity).is_err());
+
+        // Новые сущности занимают только пропущенные слоты, затем растут дальше
+        let mut fresh: Vec<i32> = (0..4).map(|_| store.insert(entity)).map(slot_of).collect();
+        fresh.sort_unstable();
+        assert_eq!(fresh, [0, 1, 3, 4]);
+        assert_eq!(slot_of(store.insert(entity)), 6);
+        assert_eq!(store.len(), 7);
+        assert!(store.iter().all(|e| store.get(e.id).is_some_and(|found| std::ptr::eq(found, e))));
+        assert_eq!(generation_of(late), 3);
+    }
+}
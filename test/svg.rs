@@ -0,0 +1,272 @@
+// Вид сверху (плоскость XZ) мира и дерева в SVG — для просмотра раскладки уровня и качества BVH в браузере.
+// Ось X мира идёт вправо, ось Z — вниз; Y отбрасывается.
+use crate::Aabb;
+use crate::Vec3;
+use crate::filter::QueryFilter;
+use crate::ray::Ray;
+use crate::world::World;
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+// Цвет внутреннего узла — по высоте (листья = 0), от холодного к тёплому
+const HEIGHT_PALETTE: [&str; 8] = [
+    "#4f7cff", "#3fb6e8", "#3fd6a0", "#8fdc4a", "#e8d03f", "#f59a3a", "#f0603a", "#d43a8c",
+];
+
+pub struct SvgOptions<'a> {
+    pub scale: f32,                      // пикселей на единицу мира
+    pub padding: f32,                    // поля вокруг уровня, в единицах мира
+    pub draw_nodes: bool,                // внутренние узлы BVH
+    pub draw_labels: bool,               // подписи id/слои у сущностей, имена слоёв из world.layers
+    pub query: Option<Aabb>,
+    pub ray: Option<(Vec3, Vec3, f32)>,  // origin, direction, длина отрисовки
+    pub filter: QueryFilter<'a>,         // какие сущности подсвечивать как попадания query/ray
+}
+
+impl Default for SvgOptions<'_> {
+    fn default() -> Self {
+        Self {
+            scale: 20.0,
+            padding: 2.0,
+            draw_nodes: true,
+            draw_labels: true,
+            query: None,
+            ray: None,
+            filter: QueryFilter::any(),
+        }
+    }
+}
+
+struct Frame {
+    min: Vec3,
+    scale: f32,
+}
+
+impl Frame {
+    fn x(&self, x: f32) -> f32 { (x - self.min.x) * self.scale }
+    fn y(&self, z: f32) -> f32 { (z - self.min.z) * self.scale }
+    fn rect(&self, out: &mut String, b: &Aabb, attrs: &str, title: Option<&str>) {
+        let _ = write!(
+            out,
+            r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" {}"#,
+            self.x(b.min.x),
+            self.y(b.min.z),
+            (b.max.x - b.min.x) * self.scale,
+            (b.max.z - b.min.z) * self.scale,
+            attrs
+        );
+        match title {
+            Some(t) => { let _ = writeln!(out, "><title>{}</title></rect>", escape(t)); }
+            None => { let _ = writeln!(out, "/>"); }
+        }
+    }
+    // Многоугольник по точкам плоскости XZ (x, z)
+    fn polygon(&self, out: &mut String, points: &[(f32, f32)], attrs: &str, title: &str) {
+        let coords: Vec<String> = points.iter().map(|&(x, z)| format!("{:.2},{:.2}", self.x(x), self.y(z))).collect();
+        let _ = writeln!(out, r#"<polygon points="{}" {}><title>{}</title></polygon>"#, coords.join(" "), attrs, escape(title));
+    }
+}
+
+// Выпуклая оболочка точек (монотонная цепочка Эндрю); почти коллинеарные точки отбрасываются,
+// нечисловые (NaN-поворот из испорченного сохранения) тоже
+fn convex_hull(mut points: Vec<(f32, f32)>) -> Vec<(f32, f32)> {
+    points.retain(|p| p.0.is_finite() && p.1.is_finite());
+    points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    points.dedup();
+    if points.len() < 3 { return points; }
+    let cross = |o: (f32, f32), a: (f32, f32), b: (f32, f32)| (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0);
+    let chain = |points: &mut dyn Iterator<Item = (f32, f32)>| {
+        let mut chain: Vec<(f32, f32)> = Vec::new();
+        for p in points {
+            while chain.len() >= 2 && cross(chain[chain.len() - 2], chain[chain.len() - 1], p) <= 1e-6 { chain.pop(); }
+            chain.push(p);
+        }
+        chain.pop();
+        chain
+    };
+    let mut hull = chain(&mut points.iter().copied());
+    hull.extend(chain(&mut points.iter().rev().copied()));
+    hull
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+pub fn world_to_svg(world: &World, options: &SvgOptions) -> String {
+    // Рамка — всё, что рисуем: дерево, сущности, запрос и луч
+    let mut bounds: Option<Aabb> = None;
+    let mut grow = |b: &Aabb| match &mut bounds {
+        Some(acc) => acc.merge(b),
+        None => bounds = Some(*b),
+    };
+    for (_, tree) in world.trees() {
+        if tree.root != -1 { grow(&tree.nodes[tree.root as usize].bbox); }
+    }
+    for e in &world.registry { grow(&e.get_aabb()); }
+    if let Some(q) = &options.query { grow(q); }
+    if let Some((origin, dir, len)) = options.ray {
+        let end = origin + dir.normalize_or_zero() * len;
+        grow(&Aabb::new(origin.min(end), origin.max(end)));
+    }
+    let mut bounds = bounds.unwrap_or(Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0)));
+    bounds.min -= Vec3::splat(options.padding);
+    bounds.max += Vec3::splat(options.padding);
+
+    let frame = Frame { min: bounds.min, scale: options.scale };
+    let width = (bounds.max.x - bounds.min.x) * options.scale;
+    let height = (bounds.max.z - bounds.min.z) * options.scale;
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.2} {:.2}" font-family="monospace" font-size="10">"#,
+        width, height, width, height
+    );
+    let _ = writeln!(out, r##"<rect width="100%" height="100%" fill="#1a1c22"/>"##);
+
+    // Попадания запроса и луча — чтобы подсветить сущности
+    let mut hits = Vec::new();
+    if let Some(q) = &options.query { world.query(q, &options.filter, &mut hits); }
+    if let Some((origin, dir, _)) = options.ray {
+        hits.extend(world.ray_hits(&Ray::new(origin, dir), &options.filter).into_iter().map(|(_, id)| id));
+    }
+
+    for (name, tree) in world.trees() {
+        if !options.draw_nodes || tree.root == -1 { continue; }
+        let _ = writeln!(out, r#"<g id="bvh-{}" fill="none">"#, name);
+        // Сначала корень, потом дети — вложенные контуры ложатся поверх
+        let mut stack = vec![tree.root];
+        while let Some(idx) = stack.pop() {
+            let node = &tree.nodes[idx as usize];
+            if node.is_leaf { continue; }
+            let color = HEIGHT_PALETTE[(node.height as usize).clamp(1, HEIGHT_PALETTE.len()) - 1];
+            frame.rect(
+                &mut out,
+                &node.bbox,
+                &format!(r#"stroke="{}" stroke-width="{:.2}" stroke-opacity="0.8""#, color, 0.5 + 0.25 * node.height as f32),
+                Some(&format!("{} node {} h={}", name, idx, node.height)),
+            );
+            stack.push(node.child1);
+            stack.push(node.child2);
+        }
+        let _ = writeln!(out, "</g>");
+    }
+
+    let _ = writeln!(out, r#"<g id="entities">"#);
+    for id in world.registry.sorted_ids() {
+        let e = &world.registry[id];
+        let b = e.get_aabb();
+        let kind = world.layers.describe(e.category);
+        let (fill, stroke) = if hits.contains(&id) { ("#ff40ff", "#ffffff") } else { ("#6a7a90", "#c8d0dc") };
+        let attrs = format!(r#"fill="{}" fill-opacity="0.6" stroke="{}" stroke-width="1""#, fill, stroke);
+        let title = format!("#{} {} pos=({:.2}, {:.2}, {:.2})", id, kind, e.pos.x, e.pos.y, e.pos.z);
+        // Повёрнутая сущность — её настоящий след на плоскости, а не AABB
+        match e.rotation {
+            None => frame.rect(&mut out, &b, &attrs, Some(&title)),
+            Some(_) => {
+                let footprint = convex_hull(e.get_obb().corners().iter().map(|c| (c.x, c.z)).collect());
+                frame.polygon(&mut out, &footprint, &attrs, &title);
+            }
+        }
+        if options.draw_labels {
+            let _ = writeln!(
+                out,
+                r##"<text x="{:.2}" y="{:.2}" fill="#e8e8e8">{} {}</text>"##,
+                frame.x(b.max.x) + 2.0,
+                frame.y(b.min.z) + 9.0,
+                id,
+                escape(&kind)
+            );
+        }
+    }
+    let _ = writeln!(out, "</g>");
+
+    if let Some(q) = &options.query {
+        frame.rect(&mut out, q, r##"fill="none" stroke="#ffffff" stroke-width="1.5" stroke-dasharray="6 3""##, Some("query"));
+    }
+    if let Some((origin, dir, len)) = options.ray {
+        let end = origin + dir.normalize_or_zero() * len;
+        let _ = writeln!(
+            out,
+            r##"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="#ffff60" stroke-width="1.5"/>"##,
+            frame.x(origin.x), frame.y(origin.z), frame.x(end.x), frame.y(end.z)
+        );
+        let _ = writeln!(out, r##"<circle cx="{:.2}" cy="{:.2}" r="3" fill="#ffff60"/>"##, frame.x(origin.x), frame.y(origin.z));
+    }
+    let _ = writeln!(out, "</svg>");
+    out
+}
+
+pub fn write_svg(world: &World, options: &SvgOptions, path: &Path) -> io::Result<()> {
+    std::fs::write(path, world_to_svg(world, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SvgOptions, convex_hull, world_to_svg};
+    use crate::Aabb;
+    use crate::Vec3;
+    use crate::world::World;
+    use glam::Quat;
+
+    #[test]
+    fn rotated_entities_are_drawn_as_their_footprint() {
+        let mut world = World::new();
+        let id = world.create_entity(Vec3::ZERO, Vec3::new(2.0, 1.0, 2.0), 1, 0);
+        world.set_rotation(id, Some(Quat::from_rotation_y(std::f32::consts::FRAC_PI_4)));
+        let svg = world_to_svg(&world, &SvgOptions { draw_nodes: false, ..Default::default() });
+        assert!(!svg.contains(r#"<rect x"#), "{}", svg);
+
+        // Квадрат 2x2, повёрнутый на 45°, — ромб с вершинами на расстоянии √2 от центра
+        let start = svg.find(r#"<polygon points=""#).expect("polygon") + r#"<polygon points=""#.len();
+        let points: Vec<(f32, f32)> = svg[start..].split('"').next().unwrap()
+            .split(' ')
+            .map(|p| p.split_once(',').map(|(x, y)| (x.parse().unwrap(), y.parse().unwrap())).unwrap())
+            .collect();
+        assert_eq!(points.len(), 4, "{}", svg);
+        let (cx, cy) = (points.iter().map(|p| p.0).sum::<f32>() / 4.0, points.iter().map(|p| p.1).sum::<f32>() / 4.0);
+        for (x, y) in points {
+            let r = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt() / 20.0;
+            assert!((r - 2f32.sqrt()).abs() < 0.01, "vertex at {} from the centre", r);
+        }
+        assert!(svg.contains(&format!("<title>#{} static pos=(0.00, 0.00, 0.00)</title></polygon>", id)));
+    }
+
+    #[test]
+    fn hull_skips_non_finite_points() {
+        let square = vec![(0.0, 0.0), (1.0, 0.0), (f32::NAN, 0.5), (1.0, 1.0), (0.0, f32::INFINITY), (0.0, 1.0), (0.5, 0.5)];
+        assert_eq!(convex_hull(square), [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        assert!(convex_hull(vec![(f32::NAN, f32::NAN); 4]).is_empty());
+    }
+
+    #[test]
+    fn entities_nodes_and_query_hits() {
+        let mut world = World::new();
+        let (cat, mask) = (world.layers.add("hot&cold").unwrap(), 0);
+        let a = world.create_entity(Vec3::ZERO, Vec3::splat(2.0), cat, mask);
+        let b = world.create_entity(Vec3::new(5.0, 0.0, 0.0), Vec3::splat(2.0), cat, mask);
+        let options = SvgOptions {
+            query: Some(Aabb::new(Vec3::new(3.5, -1.0, -1.0), Vec3::new(6.5, 1.0, 1.0))),
+            ray: Some((Vec3::new(5.0, 0.0, -2.0), Vec3::Z, 1.0)),
+            ..Default::default()
+        };
+        let svg = world_to_svg(&world, &options);
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+
+        // Рамка: корень дерева (боксы с запасом 0.2) и луч плюс поля 2 — начало в (-3.2, -4), 20 пикселей на единицу
+        assert!(svg.contains(r##"<rect x="44.00" y="60.00" width="40.00" height="40.00" fill="#6a7a90""##), "{}", svg);
+        assert!(svg.contains(r##"<rect x="144.00" y="60.00" width="40.00" height="40.00" fill="#ff40ff""##), "{}", svg);
+        assert!(svg.contains(&format!("<title>#{} hot&amp;cold pos=(0.00, 0.00, 0.00)</title>", a)));
+        assert!(svg.contains(&format!(">{} hot&amp;cold</text>", b)));
+        assert_eq!(svg.matches(r#"<g id="bvh-dynamic""#).count(), 1);
+        assert!(!svg.contains(r#"<g id="bvh-static""#), "empty static tree is not drawn");
+        assert!(svg.contains("<title>dynamic node "));
+        assert!(svg.contains(r#"stroke-dasharray="6 3"><title>query</title>"#));
+        assert!(svg.contains(r#"<line x1="164.00" y1="40.00" x2="164.00" y2="60.00""#));
+
+        let bare = world_to_svg(&world, &SvgOptions { draw_nodes: false, draw_labels: false, ..Default::default() });
+        assert!(!bare.contains("<text") && !bare.contains("bvh-") && !bare.contains("#ff40ff"));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::bvhcheck::validate_bvh;
+use crate::world::World;
+
+// Согласованность World: реестр, прокси сущностей и листья дерева смотрят друг на друга
+pub fn validate_world(world: &World) -> Result<(), String> {
+    let mut leaves = 0;
+    for (name, tree) in world.trees() {
+        leaves += validate_bvh(tree).map_err(|e| format!("{} tree: {}", name, e))?;
+    }
+    if leaves != world.registry.len() {
+        return Err(format!("trees have {} leaves for {} entities", leaves, world.registry.len()));
+    }
+    for e in &world.registry {
+        let (id, node_idx) = (e.id, e.proxy);
+        if world.registry.get(id).is_none_or(|found| !std::ptr::eq(found, e)) {
+            return Err(format!("entity {} is not reachable by its id", id));
+        }
+        // Лист ищем в дереве своего вида: статика и подвижные в одном дереве не смешиваются
+        let node = world.tree(e.body).nodes.get(node_idx as usize).ok_or(format!("entity {} maps to bad node {}", id, node_idx))?;
+        if !node.is_leaf || node.object_index != id {
+            return Err(format!("node {} does not point back at entity {}", node_idx, id));
+        }
+        if e.parent != -1 {
+            let Some(parent) = world.registry.get(e.parent) else {
+                return Err(format!("entity {} has unknown parent {}", id, e.parent));
+            };
+            if !parent.children.contains(&id) {
+                return Err(format!("entity {} is not among the children of its parent {}", id, e.parent));
+            }
+            if parent.gameplay.is_dirty && !e.gameplay.is_dirty {
+                return Err(format!("entity {} outlives its parent {} pending deletion", id, e.parent));
+            }
+            let expected = parent.pos + parent.rotation.unwrap_or_default() * e.local_pos;
+            if (expected - e.pos).length() > 1e-3 * (1.0 + expected.length()) {
+                return Err(format!("entity {} at {} drifted from its parent transform {}", id, e.pos, expected));
+            }
+        }
+        if let Some(c) = e.children.iter().find(|&&c| world.registry.get(c).is_none_or(|c| c.parent != id)) {
+            return Err(format!("child {} of entity {} does not point back", c, id));
+        }
+        // Цепочка родителей не длиннее числа сущностей, иначе это цикл
+        let (mut up, mut depth) = (e.parent, 0);
+        while up != -1 && depth <= world.registry.len() {
+            up = world.registry.get(up).map_or(-1, |p| p.parent);
+            depth += 1;
+        }
+        if up != -1 {
+            return Err(format!("entity {} is part of a parent cycle", id));
+        }
+        // Лист грязной сущности обновится только в World::flush
+        if !world.dirty.contains_key(&id) && !node.bbox.contains(e.get_aabb()) {
+            return Err(format!("leaf {} does not contain entity {} aabb", node_idx, id));
+        }
+    }
+    if let Some(id) = world.dirty.keys().find(|&&id| !world.registry.contains(id)) {
+        return Err(format!("dirty set holds unknown entity {}", id));
+    }
+    if let Some(id) = world.recently_moved.iter().find(|&&id| !world.registry.contains(id)) {
+        return Err(format!("recently moved set holds unknown entity {}", id));
+    }
+    if let Some(id) = world.components.owners().find(|&id| !world.registry.contains(id)) {
+        return Err(format!("components left behind by removed entity {}", id));
+    }
+    for id in &world.que_delete {
+        match world.registry.get(*id) {
+            Some(e) if e.gameplay.is_dirty => {}
+            Some(_) => return Err(format!("entity {} queued for deletion but not dirty", id)),
+            None => return Err(format!("deletion queue holds unknown entity {}", id)),
+        }
+    }
+    Ok(())
+}
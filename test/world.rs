@@ -1,107 +1,448 @@
 use crate::Aabb;
 use crate::DynamicBvh;
-use crate::Stack;
+use crate::dynbvh::UpdateStrategy;
 use crate::Vec3;
-use crate::entity::Entity;
-use std::collections::HashMap;
+use crate::components::{ComponentSet, Components};
+use crate::entity::{Activity, BodyKind, DamageZone, Entity, Lifetime};
+use crate::events::{Event, EventBus};
+use crate::filter::QueryFilter;
+use crate::layers::CollisionLayers;
+use crate::ray::Ray;
+use crate::store::EntityStore;
+use glam::{DVec3, Quat};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 pub struct World {
-    pub bvh: DynamicBvh,
-    pub registry: HashMap<i32, Entity>, //std::map<int, std::unique_ptr<Entity>> registry;
-    pub entity_to_node: HashMap<i32, i32>, //std::map<int, int> entityToNode;
-    pub next_id: i32,                   //int nextId = 0;
+    pub bvh: DynamicBvh,                // подвижные сущности (Dynamic, Kinematic)
+    pub static_bvh: DynamicBvh,         // неподвижные (BodyKind::Static): собирается по SAH в build_static, движения его не трясут
+    pub registry: EntityStore,          // slot map; лист BVH хранится в самой сущности (Entity::proxy)
     pub que_delete: Vec<i32>,           //std::vector<int> deletionQueue;
+    pub origin: DVec3,                  // плавающее начало координат: локальные f32 позиции отсчитываются от него
+    pub layers: CollisionLayers,        // имена слоёв и матрица столкновений, см. layers.rs
+    pub components: Components,         // произвольные данные сущностей по типам, см. components.rs
+    pub time: f64,                      // секунды, прошедшие через step
+    pub events: EventBus,               // очередь событий и именованные обработчики, см. events.rs
+    pub trigger_contacts: HashSet<(i32, i32)>, // (триггер, кто внутри) на прошлом step — чтобы Triggered шёл только при входе
+    pub dirty: BTreeMap<i32, Vec3>,     // изменённые через set_*, но ещё не обновлённые в дереве: id -> накопленное смещение
+    pub recently_moved: BTreeSet<i32>,  // сдвинутые или созданные с прошлого step — будят соседей, см. activity.rs
+    pub sleep_after: u32,               // = 60; столько step без движения до засыпания, 0 — не засыпать
+    pub wake_margin: f32,               // = 0.5; насколько далеко от сдвинутой сущности просыпаются спящие
+    pub gravity: Vec3,                  // = (0, -9.81, 0); ускорение твёрдых тел, см. physics.rs
+    pub physics_dt: f32,                // = 1/60; фиксированный шаг физики
+    pub physics_time: f32,              // накопленное step_physics время, которого не хватило на целый шаг
+    pub physics_dropped: f64,           // время, выброшенное step_physics сверх MAX_SUBSTEPS шагов за вызов
 }
 #[rustfmt::skip]
 impl World {
     pub fn new() -> Self {
         Self {
-            bvh: DynamicBvh {
-                nodes: Vec::new(),
-                root: -1,
-                free_list: -1,
-                margin: 0.2,
-            },
-            registry: HashMap::new(),
-            entity_to_node: HashMap::new(),
-            next_id: 0,
+            bvh: DynamicBvh::new(),
+            // Статике запас на движение не нужен
+            static_bvh: DynamicBvh { margin: 0.0, ..DynamicBvh::new() },
+            registry: EntityStore::new(),
             que_delete: Vec::new(),
+            origin: DVec3::ZERO,
+            layers: CollisionLayers::with_defaults(),
+            components: Components::new(),
+            time: 0.0,
+            events: EventBus::new(),
+            trigger_contacts: HashSet::new(),
+            dirty: BTreeMap::new(),
+            recently_moved: BTreeSet::new(),
+            sleep_after: 60,
+            wake_margin: 0.5,
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            physics_dt: 1.0 / 60.0,
+            physics_time: 0.0,
+            physics_dropped: 0.0,
         }
     }
     pub fn create_entity(&mut self, pos: Vec3, size: Vec3, cat: i32, mask: i32) -> i32 {
-        self.next_id += 1;
-        let id = self.next_id;
-        let e = Entity::new(id, pos, size, cat, mask);
-        self.entity_to_node
-            .insert(id, self.bvh.insert_leaf(id, &e.get_aabb()));
-        self.registry.insert(id, e);
-        return id;
+        let id = self.registry.insert(|id| Entity::new(id, pos, size, cat, mask));
+        self.attach_proxy(id);
+        self.recently_moved.insert(id);
+        self.events.emit(Event::Spawned { id });
+        id
+    }
+    // То же с заданным id — для загрузки сохранений, чтобы ссылки на сущности оставались верными
+    pub fn create_entity_with_id(&mut self, id: i32, pos: Vec3, size: Vec3, cat: i32, mask: i32) -> Result<(), String> {
+        self.registry.insert_with_id(id, |id| Entity::new(id, pos, size, cat, mask))?;
+        self.attach_proxy(id);
+        self.recently_moved.insert(id);
+        self.events.emit(Event::Spawned { id });
+        Ok(())
+    }
+    fn attach_proxy(&mut self, id: i32) {
+        let (aabb, body) = (self.registry[id].get_aabb(), self.registry[id].body);
+        let tree = self.tree_mut(body);
+        let proxy = tree.create_proxy(id, &aabb, tree.margin);
+        self.registry[id].proxy = proxy;
+    }
+    // Дерево, где лежат листья сущностей этого вида
+    pub fn tree(&self, body: BodyKind) -> &DynamicBvh {
+        if body == BodyKind::Static { &self.static_bvh } else { &self.bvh }
+    }
+    fn tree_mut(&mut self, body: BodyKind) -> &mut DynamicBvh {
+        if body == BodyKind::Static { &mut self.static_bvh } else { &mut self.bvh }
+    }
+    // Оба дерева с подписями — для статистики и отладочного вывода
+    pub fn trees(&self) -> [(&'static str, &DynamicBvh); 2] {
+        [("static", &self.static_bvh), ("dynamic", &self.bvh)]
+    }
+    // Переводит сущность в другое дерево, если вид этого требует. Свой margin листа при этом сбрасывается.
+    pub fn set_body(&mut self, id: i32, body: BodyKind) -> bool {
+        let Some(e) = self.registry.get_mut(id) else { return false };
+        let (old, proxy) = (e.body, e.proxy);
+        e.body = body;
+        if (old == BodyKind::Static) != (body == BodyKind::Static) {
+            self.tree_mut(old).remove_leaf(proxy);
+            self.attach_proxy(id);
+        }
+        true
+    }
+    // Пересборка статического дерева по SAH — после загрузки уровня или расстановки статики
+    pub fn build_static(&mut self) {
+        self.static_bvh.rebuild();
+    }
+    // Пакет перемещений (id, смещение): статические и подвижные листья обновляются каждый в своём дереве.
+    // Возвращает, как обновилось дерево подвижных.
+    fn update_trees(&mut self, moved: &[(i32, Vec3)]) -> UpdateStrategy {
+        let (mut dynamic, mut fixed) = (Vec::new(), Vec::new());
+        for &(id, displacement) in moved {
+            let Some(e) = self.registry.get(id) else { continue };
+            let batch = if e.body == BodyKind::Static { &mut fixed } else { &mut dynamic };
+            batch.push((e.proxy, e.get_aabb(), displacement));
+        }
+        self.static_bvh.update_proxies(&fixed);
+        self.bvh.update_proxies(&dynamic)
     }
     pub fn update_position(&mut self, id: i32, npos: Vec3) {
-        if let Some(entity) = self.registry.get_mut(&id) {
+        // Уже ждёт обновления дерева — просто докладываем к отложенному
+        if self.dirty.contains_key(&id) {
+            let size = self.registry[id].size;
+            self.set_transform(id, npos, size);
+            return;
+        }
+        if let Some(entity) = self.registry.get_mut(id) {
+            // Стоит на месте — ни события, ни пробуждения соседей
+            if entity.pos == npos { return; }
+            let displacement = npos - entity.pos;
+            self.events.emit(Event::Moved { id, from: entity.pos, to: npos });
             entity.pos = npos;
-            let real_aabb = entity.get_aabb();
-
-            // 1. Получаем текущий индекс узла (копируем его, чтобы отпустить ссылку на hashmap)
-            let old_node_idx = *self.entity_to_node.get(&id).expect("Entity not in BVH");
-
-            // 2. Проверяем, нужно ли обновление
-            if !self.bvh.nodes[old_node_idx as usize]
-                .bbox
-                .contains(real_aabb)
-            {
-                // Удаляем старый
-                self.bvh.remove_leaf(old_node_idx);
-
-                // Вставляем новый (с запасом margin!)
-                let fat_aabb = Aabb::new(
-                    real_aabb.min - Vec3::splat(self.bvh.margin),
-                    real_aabb.max + Vec3::splat(self.bvh.margin),
-                );
-                let new_node_idx = self.bvh.insert_leaf(id, &fat_aabb);
-
-                // Обновляем мапу
-                self.entity_to_node.insert(id, new_node_idx);
+            entity.world_pos = entity.world_pos.map(|_| self.origin + npos.as_dvec3());
+            let (real_aabb, proxy, body) = (entity.get_aabb(), entity.proxy, entity.body);
+
+            // Индекс прокси при перевставке не меняется, entity.proxy трогать не нужно
+            self.tree_mut(body).move_proxy(proxy, &real_aabb, displacement);
+            // Дети едут следом, одним пакетом
+            self.touch(id);
+            self.sync_local(id);
+            let subtree = self.propagate(id, false);
+            if !subtree.is_empty() { self.update_trees(&subtree); }
+        }
+    }
+    // Пакетное перемещение: дерево само выбирает между перевставкой, refit и пересборкой
+    pub fn update_positions(&mut self, moves: &[(i32, Vec3)]) -> UpdateStrategy {
+        self.flush();
+        let mut moved = Vec::with_capacity(moves.len());
+        for &(id, npos) in moves {
+            if let Some(entity) = self.registry.get_mut(id) {
+                if entity.pos == npos { continue; }
+                let displacement = npos - entity.pos;
+                self.events.emit(Event::Moved { id, from: entity.pos, to: npos });
+                entity.pos = npos;
+                entity.world_pos = entity.world_pos.map(|_| self.origin + npos.as_dvec3());
+                moved.push((id, displacement));
             }
         }
+        // Сначала новые смещения всех сдвинутых, потом их поддеревья — в тот же пакет
+        let roots: Vec<i32> = moved.iter().map(|m| m.0).collect();
+        for &id in &roots {
+            self.touch(id);
+            self.sync_local(id);
+        }
+        for &id in &roots { moved.extend(self.propagate(id, false)); }
+        self.update_trees(&moved)
     }
-    pub fn query(&self, bbox: &Aabb, out: &mut Vec<i32>) {
-        if self.bvh.root == -1 { return; }
-
-        let mut stack = Stack::new();
-        stack.push(self.bvh.root);
-
-        while !stack.is_empty() {
+    // Изменения позиции, размера и слоёв откладываются: сущность попадает в dirty, а дерево
+    // обновляется одним пакетом в flush (его зовут step и apply_commands). Запросы до flush всё равно
+    // точны — грязные сущности проверяются напрямую, мимо дерева.
+    pub fn set_transform(&mut self, id: i32, pos: Vec3, size: Vec3) -> bool {
+        let Some(e) = self.registry.get_mut(id) else { return false };
+        if e.pos != pos { self.events.emit(Event::Moved { id, from: e.pos, to: pos }); }
+        *self.dirty.entry(id).or_insert(Vec3::ZERO) += pos - e.pos;
+        e.pos = pos;
+        e.world_pos = e.world_pos.map(|_| self.origin + pos.as_dvec3());
+        e.size = size;
+        self.touch(id);
+        self.sync_local(id);
+        self.propagate(id, true);
+        true
+    }
+    pub fn set_size(&mut self, id: i32, size: Vec3) -> bool {
+        let Some(pos) = self.registry.get(id).map(|e| e.pos) else { return false };
+        self.set_transform(id, pos, size)
+    }
+    // None — снова выровнен по осям
+    pub fn set_rotation(&mut self, id: i32, rotation: Option<Quat>) -> bool {
+        let Some(e) = self.registry.get_mut(id) else { return false };
+        e.rotation = rotation;
+        self.dirty.entry(id).or_insert(Vec3::ZERO);
+        self.touch(id);
+        self.sync_local(id);
+        self.propagate(id, true);
+        true
+    }
+    pub fn set_layers(&mut self, id: i32, category: i32, mask: i32) -> bool {
+        let Some(e) = self.registry.get_mut(id) else { return false };
+        e.category = category;
+        e.mask = mask;
+        // Дерево слои не хранит, но flush заодно пересмотрит контакты триггеров
+        self.dirty.entry(id).or_insert(Vec3::ZERO);
+        self.touch(id);
+        true
+    }
+    // Одно обновление дерева на все отложенные изменения
+    pub fn flush(&mut self) -> UpdateStrategy {
+        let dirty = std::mem::take(&mut self.dirty);
+        let batch: Vec<(i32, Vec3)> = dirty.iter().map(|(&id, &displacement)| (id, displacement)).collect();
+        let strategy = self.update_trees(&batch);
+        for &id in dirty.keys() { self.prune_trigger_contacts(id); }
+        strategy
+    }
+    // Убирает из кэша контакты триггеров с участием id, которые больше не выполняются (размер или слои сменились)
+    fn prune_trigger_contacts(&mut self, id: i32) {
+        let stale: Vec<(i32, i32)> = self.trigger_contacts.iter()
+            .filter(|&&(trigger, other)| trigger == id || other == id)
+            .filter(|&&(trigger, other)| {
+                match (self.registry.get(trigger), self.registry.get(other)) {
+                    (Some(t), Some(o)) => !(self.filter_for(trigger).accepts(&self.layers, o) && t.overlaps(o)),
+                    _ => true,
+                }
+            })
+            .copied()
+            .collect();
+        for pair in stale { self.trigger_contacts.remove(&pair); }
+    }
+    // Компонент только для живой сущности; возвращает прежнее значение, если оно было
+    pub fn insert_component<T: 'static>(&mut self, id: i32, value: T) -> Result<Option<T>, String> {
+        if !self.registry.contains(id) {
+            return Err(format!("no entity {}", id));
+        }
+        Ok(self.components.insert(id, value))
+    }
+    pub fn remove_component<T: 'static>(&mut self, id: i32) -> Option<T> {
+        self.components.remove(id)
+    }
+    pub fn component<T: 'static>(&self, id: i32) -> Option<&T> {
+        self.components.get(id)
+    }
+    pub fn component_mut<T: 'static>(&mut self, id: i32) -> Option<&mut T> {
+        self.components.get_mut(id)
+    }
+    // Живые сущности со всеми компонентами набора, например world.with::<(Lifetime, Damage)>()
+    pub fn with<S: ComponentSet>(&self) -> Vec<i32> {
+        self.components.ids_with::<S>()
+    }
+    // Запас "толстого" AABB для конкретной сущности (по умолчанию bvh.margin)
+    pub fn set_margin(&mut self, id: i32, margin: f32) {
+        if let Some((proxy, body)) = self.registry.get(id).map(|e| (e.proxy, e.body)) {
+            self.tree_mut(body).set_margin(proxy, margin);
+        }
+    }
+    pub fn to_local(&self, world_pos: DVec3) -> Vec3 {
+        (world_pos - self.origin).as_vec3()
+    }
+    pub fn to_world(&self, local: Vec3) -> DVec3 {
+        self.origin + local.as_dvec3()
+    }
+    // Сущность с точной позицией в мире: при сдвиге начала координат её pos пересчитывается из world_pos без накопления ошибки
+    pub fn create_entity_at(&mut self, world_pos: DVec3, size: Vec3, cat: i32, mask: i32) -> i32 {
+        let id = self.create_entity(self.to_local(world_pos), size, cat, mask);
+        self.registry[id].world_pos = Some(world_pos);
+        id
+    }
+    // Перемещение в точку мира; world_pos запоминается точной, а не восстановленной из f32 pos
+    pub fn set_world_position(&mut self, id: i32, world_pos: DVec3) {
+        self.update_position(id, self.to_local(world_pos));
+        if let Some(entity) = self.registry.get_mut(id) {
+            entity.world_pos = Some(world_pos);
+        }
+    }
+    // Переносит начало координат в точку origin + offset: все позиции и боксы дерева уменьшаются на offset
+    // за один проход, без перестройки дерева.
+    pub fn shift_origin(&mut self, offset: Vec3) {
+        self.origin += offset.as_dvec3();
+        self.bvh.shift_origin(offset);
+        self.static_bvh.shift_origin(offset);
 
-            let mut node_idx = 0;
-            if let Some(p) = stack.pop(){
-                node_idx=p;
+        let mut grown = [false; 2];
+        for entity in self.registry.iter_mut() {
+            entity.pos = match entity.world_pos {
+                Some(wp) => (wp - self.origin).as_vec3(),
+                None => entity.pos - offset,
             };
-            let node = &self.bvh.nodes[node_idx as usize];
-
-            // Проверка на пересечение (Intersects), а не на удержание (Contains)
-            if node.bbox.min.x > bbox.max.x || node.bbox.max.x < bbox.min.x ||
-               node.bbox.min.y > bbox.max.y || node.bbox.max.y < bbox.min.y ||
-               node.bbox.min.z > bbox.max.z || node.bbox.max.z < bbox.min.z {
-                continue;
+            // Округление при сдвиге может вытолкнуть точный AABB за лист на ulp — тогда расширяем лист
+            let is_static = entity.body == BodyKind::Static;
+            let tree = if is_static { &mut self.static_bvh } else { &mut self.bvh };
+            let leaf = &mut tree.nodes[entity.proxy as usize];
+            let aabb = entity.get_aabb();
+            if !leaf.bbox.contains(aabb) {
+                leaf.bbox.merge(&aabb);
+                grown[is_static as usize] = true;
             }
+        }
+        if grown[0] { self.bvh.refit(); }
+        if grown[1] { self.static_bvh.refit(); }
+    }
+    // Фильтр запросов от имени сущности: её слои и то, что она ищет; сама она в результаты не попадает
+    pub fn filter_for(&self, id: i32) -> QueryFilter<'static> {
+        match self.registry.get(id) {
+            Some(e) => QueryFilter::any().layers(e.category, e.mask).ignore(id),
+            None => QueryFilter::any(),
+        }
+    }
+    pub fn query(&self, bbox: &Aabb, filter: &QueryFilter, out: &mut Vec<i32>) {
+        // Дерево отдаёт кандидатов по "толстым" листьям — на листе проверяем фильтр и точную форму (AABB или OBB).
+        // Листья грязных сущностей могут отставать, их проверяем отдельно. Статика и подвижные — в разных деревьях.
+        let hit = |id: i32| self.registry.get(id).is_some_and(|e| filter.accepts(&self.layers, e) && e.overlaps_aabb(bbox));
+        self.static_bvh.query_filtered(bbox, |id| !self.dirty.contains_key(&id) && hit(id), out);
+        self.bvh.query_filtered(bbox, |id| !self.dirty.contains_key(&id) && hit(id), out);
+        out.extend(self.dirty.keys().copied().filter(|&id| hit(id)));
+    }
+    // Все попадания луча (бесконечного) в точные боксы сущностей, ближние первыми: (расстояние, id)
+    pub fn ray_hits(&self, ray: &Ray, filter: &QueryFilter) -> Vec<(f32, i32)> {
+        let accept = |id: i32| self.registry.get(id).is_some_and(|e| filter.accepts(&self.layers, e));
+        let mut candidates = self.static_bvh.ray_cast_filtered(ray, |id| !self.dirty.contains_key(&id) && accept(id));
+        candidates.extend(self.bvh.ray_cast_filtered(ray, |id| !self.dirty.contains_key(&id) && accept(id)));
+        candidates.extend(self.dirty.keys().copied().filter(|&id| accept(id)));
+        let mut hits: Vec<(f32, i32)> = candidates.into_iter()
+            .filter_map(|id| self.registry[id].ray_hit(ray).map(|t| (t, id)))
+            .collect();
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        hits
+    }
+    // Ближайшая сущность на отрезке p1..p2: (id, доля отрезка 0..1)
+    pub fn raycast(&self, p1: Vec3, p2: Vec3, filter: &QueryFilter) -> Option<(i32, f32)> {
+        if p1 == p2 { return None; }
+        // direction = p2 - p1, поэтому расстояние луча и есть доля отрезка
+        let ray = Ray::new(p1, p2 - p1);
+        self.ray_hits(&ray, filter).into_iter()
+            .find(|&(t, _)| t <= 1.0)
+            .map(|(t, id)| (id, t))
+    }
 
-            if node.is_leaf {
-                out.push(node.object_index);
+    // Урон сущности; возвращает оставшееся здоровье. Уже мёртвых (health <= 0) не трогает.
+    // Отрицательный урон (и NaN) считается нулевым — лечение идёт мимо урона, через gameplay.health.
+    // При смерти выпускается Event::Died, сущность уходит в очередь удаления.
+    pub fn apply_damage(&mut self, id: i32, amount: f32) -> Option<f32> {
+        let entity = self.registry.get_mut(id)?;
+        if entity.gameplay.health <= 0.0 { return Some(entity.gameplay.health); }
+        entity.gameplay.health -= amount.max(0.0);
+        let health = entity.gameplay.health;
+        if health <= 0.0 {
+            self.events.emit(Event::Died { id });
+            self.mark_for_deletion(id);
+        }
+        Some(health)
+    }
+    // Урон по площади: все, чей бокс задевает сферу. Возвращает задетых, по возрастанию id.
+    pub fn apply_area_damage(&mut self, center: Vec3, radius: f32, amount: f32, filter: &QueryFilter) -> Vec<i32> {
+        let mut hits = Vec::new();
+        self.query(&Aabb::new(center - Vec3::splat(radius), center + Vec3::splat(radius)), filter, &mut hits);
+        hits.retain(|&id| self.registry[id].distance_sq(center) <= radius * radius);
+        hits.sort_unstable();
+        for &id in &hits { self.apply_damage(id, amount); }
+        hits
+    }
+    // Урон от зон за dt. Зоны обходятся по возрастанию id, чтобы результат не зависел от порядка хранения.
+    fn apply_zone_damage(&mut self, dt: f32) {
+        let mut zones: Vec<(i32, f32)> = self.components.iter::<DamageZone>().map(|(id, z)| (id, z.per_second)).collect();
+        zones.sort_unstable_by_key(|z| z.0);
+        let mut hits = Vec::new();
+        for (zone, per_second) in zones {
+            let Some(e) = self.registry.get(zone) else { continue };
+            // Спящая зона продолжает жечь — урон идёт по времени, а не от движения
+            if e.gameplay.is_dirty || e.activity == Activity::Disabled { continue; }
+            hits.clear();
+            // Мёртвых, ещё не убранных cleanup, зона больше не трогает
+            self.query(&e.get_aabb(), &self.filter_for(zone).alive(), &mut hits);
+            // Запрос шёл по AABB зоны; повёрнутой зоне нужно перекрытие с самим OBB
+            hits.retain(|&id| e.overlaps(&self.registry[id]));
+            hits.sort_unstable();
+            for &id in &hits { self.apply_damage(id, per_second * dt); }
+        }
+    }
+    // Триггеры — сущности с on_trigger. Triggered выпускается, когда кто-то (по слоям триггера) входит в его объём.
+    // Спящий триггер по дереву не ищет — его прежние контакты только перепроверяются напрямую.
+    fn update_triggers(&mut self) {
+        let mut triggers: Vec<i32> = self.registry.iter()
+            .filter(|e| e.on_trigger.is_some() && e.activity != Activity::Disabled)
+            .map(|e| e.id)
+            .collect();
+        triggers.sort_unstable();
+        let mut contacts = HashSet::new();
+        let mut hits = Vec::new();
+        for trigger in triggers {
+            hits.clear();
+            let t = &self.registry[trigger];
+            if t.activity == Activity::Sleeping {
+                let filter = self.filter_for(trigger);
+                hits.extend(self.trigger_contacts.iter()
+                    .filter(|&&(tr, other)| tr == trigger && self.registry.get(other).is_some_and(|o| filter.accepts(&self.layers, o) && t.overlaps(o)))
+                    .map(|&(_, other)| other));
             } else {
-                // Всегда проверяйте переполнение стека, если дерево глубокое
-                stack.push(node.child1);
-                stack.push(node.child2);
+                self.query(&t.get_aabb(), &self.filter_for(trigger), &mut hits);
+                hits.retain(|&id| t.overlaps(&self.registry[id]));
+            }
+            hits.sort_unstable();
+            for &other in &hits {
+                if !self.trigger_contacts.contains(&(trigger, other)) {
+                    self.events.emit(Event::Triggered { trigger, other });
+                }
+                contacts.insert((trigger, other));
             }
         }
+        self.trigger_contacts = contacts;
     }
-
-    pub fn raycast(p1:Vec3,p2:Vec3,mask:i32) -> i32 {
-        mask
+    // Взаимодействие actor с target: событие для обработчика target.on_interact
+    pub fn interact(&mut self, actor: i32, target: i32) -> bool {
+        if !self.registry.contains(target) { return false; }
+        self.events.emit(Event::Interacted { target, actor });
+        true
+    }
+    // Шаг симуляции: засыпание и пробуждение, триггеры, урон от зон, затем стареют сущности с Lifetime; погибшие и истёкшие
+    // (с Event::Expired) уходят через обычную очередь удаления. on_expire истёкшей зовётся сразу: к dispatch_events её
+    // уже не будет, а обработчику обычно нужны её позиция и компоненты. События копятся в events до dispatch_events/drain_events.
+    // Возвращает id удалённых по времени, по возрастанию.
+    pub fn step(&mut self, dt: f32) -> Vec<i32> {
+        self.time += dt as f64;
+        self.flush();
+        self.update_activity();
+        self.update_triggers();
+        self.apply_zone_damage(dt);
+        let mut expired = Vec::new();
+        for (id, lifetime) in self.components.iter_mut::<Lifetime>() {
+            lifetime.remaining -= dt;
+            if lifetime.remaining <= 0.0 { expired.push(id); }
+        }
+        expired.sort_unstable();
+        for &id in &expired {
+            self.events.emit(Event::Expired { id });
+            if let Some(name) = self.registry.get(id).and_then(|e| e.on_expire.clone()) {
+                self.call_handler(&name, &Event::Expired { id });
+            }
+            self.mark_for_deletion(id);
+        }
+        self.cleanup();
+        expired
     }
 
+    // Вместе с сущностью удаляется всё её поддерево
     pub fn mark_for_deletion(&mut self, id: i32) {
-        if let Some(entity) = self.registry.get_mut(&id) {
-            if !entity.gameplay.is_dirty {
+        let subtree = self.descendants(id);
+        for id in std::iter::once(id).chain(subtree) {
+            if let Some(entity) = self.registry.get_mut(id)
+                && !entity.gameplay.is_dirty {
                 entity.gameplay.is_dirty = true;
                 self.que_delete.push(id);
             }
@@ -111,23 +452,246 @@ impl World {
     pub fn cleanup(&mut self) {
         // Используем drain, чтобы очистить очередь и получить ID
         for id in self.que_delete.drain(..) {
-            // 1. Удаляем из BVH
-            if let Some(node_idx) = self.entity_to_node.remove(&id) {
-                self.bvh.remove_leaf(node_idx);
+            // Удаляем сущность, а вместе с ней и её лист из BVH
+            if let Some(entity) = self.registry.remove(id) {
+                self.dirty.remove(&id);
+                self.recently_moved.remove(&id);
+                self.trigger_contacts.retain(|&(trigger, other)| trigger != id && other != id);
+                let tree = if entity.body == BodyKind::Static { &mut self.static_bvh } else { &mut self.bvh };
+                tree.remove_leaf(entity.proxy);
+                self.components.remove_entity(id);
+                if let Some(parent) = self.registry.get_mut(entity.parent) { parent.children.retain(|&c| c != id); }
+                // Дети помечены вместе с родителем, но если кто-то остался — он становится корнем
+                for &c in &entity.children {
+                    if let Some(child) = self.registry.get_mut(c) { child.parent = -1; }
+                }
+                self.events.emit(Event::Destroyed { id });
             }
-            // 2. Удаляем саму сущность
-            self.registry.remove(&id);
         }
     }
     pub fn clear_all(&mut self) {
         self.cleanup();
         self.registry.clear();
-        self.entity_to_node.clear();
+        self.components.clear();
         self.que_delete.clear();
-        self.next_id = 0;
+        self.time = 0.0;
+        self.events.clear();
+        self.trigger_contacts.clear();
+        self.dirty.clear();
+        self.recently_moved.clear();
+        self.physics_time = 0.0;
+        self.physics_dropped = 0.0;
+        self.origin = DVec3::ZERO;
         // Сброс самого BVH
-        self.bvh.nodes.clear();
-        self.bvh.root = -1;
-        self.bvh.free_list = -1;
+        self.bvh.clear();
+        self.static_bvh.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::World;
+    use crate::Vec3;
+    use crate::Aabb;
+    use crate::entity::{Activity, BodyKind, DamageZone, Lifetime};
+    use crate::events::Event;
+    use crate::filter::QueryFilter;
+    use crate::ray::Ray;
+    use crate::validate::validate_world;
+    use glam::DVec3;
+
+    #[test]
+    fn set_body_moves_the_leaf_between_trees() {
+        let mut world = World::new();
+        let id = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 1);
+        let other = world.create_entity(Vec3::new(3.0, 0.0, 0.0), Vec3::ONE, 1, 1);
+        assert!(!world.set_body(-1, BodyKind::Static));
+
+        assert!(world.set_body(id, BodyKind::Static));
+        assert_eq!((world.static_bvh.proxy_count, world.bvh.proxy_count), (1, 1));
+        let leaf = &world.static_bvh.nodes[world.registry[id].proxy as usize];
+        assert!(leaf.is_leaf && leaf.object_index == id);
+        // У статики нет запаса на движение
+        assert_eq!((leaf.bbox.min, leaf.bbox.max), (Vec3::splat(-0.5), Vec3::splat(0.5)));
+
+        // Запросы и лучи видят оба дерева; статику можно двигать — её дерево обновляется
+        let mut hits = Vec::new();
+        world.query(&Aabb::new(Vec3::splat(-1.0), Vec3::new(4.0, 1.0, 1.0)), &QueryFilter::any(), &mut hits);
+        hits.sort_unstable();
+        assert_eq!(hits, [id, other]);
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::X);
+        assert_eq!(world.ray_hits(&ray, &QueryFilter::any()).iter().map(|h| h.1).collect::<Vec<_>>(), [id, other]);
+        world.update_position(id, Vec3::new(0.0, 2.0, 0.0));
+        validate_world(&world).unwrap();
+
+        // Kinematic и Dynamic живут в одном дереве — лист не переезжает
+        let proxy = world.registry[other].proxy;
+        world.set_body(other, BodyKind::Kinematic);
+        assert_eq!((world.registry[other].proxy, world.bvh.proxy_count), (proxy, 1));
+        world.set_body(id, BodyKind::Dynamic);
+        assert_eq!((world.static_bvh.proxy_count, world.bvh.proxy_count, world.static_bvh.root), (0, 2, -1));
+        validate_world(&world).unwrap();
+    }
+
+    #[test]
+    fn build_static_lowers_sah_and_keeps_queries() {
+        let mut world = World::new();
+        // Стена из блоков в порядке, неудобном для пошаговой вставки
+        let blocks: Vec<i32> = (0..64)
+            .map(|i| {
+                let (x, z) = ((i * 37 % 64) as f32, (i * 11 % 8) as f32);
+                let id = world.create_entity(Vec3::new(x, 0.0, z), Vec3::ONE, 1, 0);
+                world.set_body(id, BodyKind::Static);
+                id
+            })
+            .collect();
+        let before = world.static_bvh.sah_cost();
+        let query = Aabb::new(Vec3::new(9.6, -1.0, -1.0), Vec3::new(20.4, 1.0, 9.0));
+        let mut expected = Vec::new();
+        world.query(&query, &QueryFilter::any(), &mut expected);
+        expected.sort_unstable();
+
+        world.build_static();
+        assert!(world.static_bvh.sah_cost() <= before, "sah {} -> {}", before, world.static_bvh.sah_cost());
+        assert_eq!(world.static_bvh.proxy_count, blocks.len() as i32);
+        let mut hits = Vec::new();
+        world.query(&query, &QueryFilter::any(), &mut hits);
+        hits.sort_unstable();
+        assert_eq!(hits, expected);
+        validate_world(&world).unwrap();
+    }
+
+    fn died(world: &mut World) -> Vec<i32> {
+        world.drain_events().into_iter().filter_map(|e| match e { Event::Died { id } => Some(id), _ => None }).collect()
+    }
+
+    #[test]
+    fn damage_kills_once_and_never_heals() {
+        let mut world = World::new();
+        let id = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 0);
+        assert_eq!(world.apply_damage(id, 30.0), Some(70.0));
+        assert_eq!(world.apply_damage(id, -50.0), Some(70.0));
+        assert_eq!(world.apply_damage(id, f32::NAN), Some(70.0));
+        assert!(died(&mut world).is_empty());
+
+        assert_eq!(world.apply_damage(id, 80.0), Some(-10.0));
+        assert!(world.registry[id].gameplay.is_dirty);
+        assert_eq!(world.apply_damage(id, 5.0), Some(-10.0), "the dead take no more damage");
+        assert_eq!(died(&mut world), [id]);
+        world.cleanup();
+        assert_eq!(world.apply_damage(id, 5.0), None);
+    }
+
+    #[test]
+    fn area_damage_hits_what_touches_the_sphere() {
+        let mut world = World::new();
+        let near = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 0);
+        let edge = world.create_entity(Vec3::new(3.0, 0.0, 0.0), Vec3::ONE, 2, 0);
+        // Угол бокса вне сферы, хотя её AABB его задевает
+        let corner = world.create_entity(Vec3::new(2.5, 2.5, 0.0), Vec3::ONE, 1, 0);
+        let far = world.create_entity(Vec3::splat(10.0), Vec3::ONE, 1, 0);
+        world.drain_events();
+
+        assert_eq!(world.apply_area_damage(Vec3::ZERO, 2.6, 40.0, &QueryFilter::any()), [near, edge]);
+        assert_eq!(world.apply_area_damage(Vec3::ZERO, 2.6, 70.0, &QueryFilter::any().exclude(2)), [near]);
+        assert_eq!(world.registry[edge].gameplay.health, 60.0);
+        assert_eq!(world.registry[corner].gameplay.health, 100.0);
+        assert_eq!(world.registry[far].gameplay.health, 100.0);
+        assert_eq!(died(&mut world), [near]);
+    }
+
+    #[test]
+    fn zones_burn_what_they_see_while_enabled() {
+        let mut world = World::new();
+        let (stat, player) = (world.layers.bit("static").unwrap(), world.layers.bit("player").unwrap());
+        let zone = world.create_entity(Vec3::ZERO, Vec3::splat(4.0), stat, player);
+        world.insert_component(zone, DamageZone { per_second: 10.0 }).unwrap();
+        let hero = world.create_entity(Vec3::ZERO, Vec3::ONE, player, 0);
+        let crate_id = world.create_entity(Vec3::ONE, Vec3::ONE, stat, 0);
+        let outside = world.create_entity(Vec3::splat(5.0), Vec3::ONE, player, 0);
+        world.drain_events();
+
+        world.step(0.5);
+        assert_eq!(world.registry[hero].gameplay.health, 95.0);
+        assert_eq!(world.registry[crate_id].gameplay.health, 100.0, "zone does not see its own layer");
+        assert_eq!(world.registry[outside].gameplay.health, 100.0);
+
+        world.set_activity(zone, Activity::Disabled);
+        world.step(0.5);
+        assert_eq!(world.registry[hero].gameplay.health, 95.0);
+
+        // Вторая зона на том же месте: умирает один раз, первой его добивает зона с меньшим id
+        world.set_activity(zone, Activity::Awake);
+        let second = world.create_entity(Vec3::ZERO, Vec3::splat(4.0), stat, player);
+        world.insert_component(second, DamageZone { per_second: 10.0 }).unwrap();
+        world.registry[hero].gameplay.health = 3.0;
+        world.drain_events();
+        world.step(0.5);
+        assert_eq!(world.registry.get(hero).map(|e| e.gameplay.health), None);
+        assert_eq!(died(&mut world), [hero]);
+    }
+
+    #[test]
+    fn lifetime_counts_down_then_expires_with_its_subtree() {
+        let mut world = World::new();
+        let shell = world.create_entity(Vec3::ZERO, Vec3::ONE, 1, 0);
+        let spark = world.create_entity(Vec3::ONE, Vec3::ONE, 1, 0);
+        world.set_parent(spark, shell).unwrap();
+        let keeper = world.create_entity(Vec3::splat(5.0), Vec3::ONE, 1, 0);
+        world.insert_component(shell, Lifetime::new(1.0)).unwrap();
+        world.insert_component(keeper, Lifetime::new(10.0)).unwrap();
+        // Обработчик застаёт сущность живой и может оставить что-то на её месте
+        world.registry[shell].on_expire = Some("burst".to_string());
+        world.events.register("burst", |world, event| {
+            let Event::Expired { id } = *event else { return };
+            let pos = world.registry[id].pos;
+            world.create_entity(pos, Vec3::splat(3.0), 1, 0);
+        });
+        world.drain_events();
+
+        assert!(world.step(0.4).is_empty());
+        assert!((world.component::<Lifetime>(shell).unwrap().remaining - 0.6).abs() < 1e-6);
+        assert!(world.drain_events().is_empty());
+
+        assert_eq!(world.step(0.6), [shell]);
+        assert!(!world.registry.contains(shell) && !world.registry.contains(spark));
+        assert!(world.component::<Lifetime>(shell).is_none());
+        assert!(world.components.owners().all(|id| id == keeper));
+        let burst: Vec<i32> = world.registry.sorted_ids().into_iter().filter(|&id| id != keeper).collect();
+        assert_eq!(burst.len(), 1);
+        assert_eq!((world.registry[burst[0]].pos, world.registry[burst[0]].size), (Vec3::ZERO, Vec3::splat(3.0)));
+        // Expired, Spawned от обработчика, потом Destroyed всего поддерева
+        let events = world.drain_events();
+        assert_eq!(events[..2], [Event::Expired { id: shell }, Event::Spawned { id: burst[0] }]);
+        assert_eq!(events.iter().filter(|e| matches!(e, Event::Destroyed { .. })).count(), 2);
+        assert!(world.que_delete.is_empty());
+        validate_world(&world).unwrap();
+    }
+
+    #[test]
+    fn shift_origin_keeps_moved_entities_in_place() {
+        let mut world = World::new();
+        let far = world.create_entity_at(DVec3::new(1000.0, 0.0, 0.0), Vec3::ONE, 1, 0);
+        let plain = world.create_entity(Vec3::new(1000.0, 5.0, 0.0), Vec3::ONE, 1, 0);
+        world.update_position(far, Vec3::new(1010.0, 0.0, 0.0));
+        world.update_positions(&[(plain, Vec3::new(1020.0, 5.0, 0.0))]);
+        assert_eq!(world.registry[far].world_pos, Some(DVec3::new(1010.0, 0.0, 0.0)));
+
+        world.shift_origin(Vec3::new(1000.0, 0.0, 0.0));
+        assert_eq!(world.registry[far].pos, Vec3::new(10.0, 0.0, 0.0));
+        assert_eq!(world.registry[plain].pos, Vec3::new(20.0, 5.0, 0.0));
+        // Лист остался у сущности, а не растянулся до её начального места
+        let leaf = world.bvh.nodes[world.registry[far].proxy as usize].bbox;
+        assert!(leaf.contains(world.registry[far].get_aabb()) && leaf.min.x > 9.0, "leaf {} .. {}", leaf.min, leaf.max);
+        validate_world(&world).unwrap();
+
+        // Отложенное перемещение и точная установка тоже держат world_pos
+        world.set_transform(far, Vec3::new(12.0, 0.0, 0.0), Vec3::ONE);
+        assert_eq!(world.registry[far].world_pos, Some(DVec3::new(1012.0, 0.0, 0.0)));
+        world.set_world_position(far, DVec3::new(1000.000001, 0.0, 0.0));
+        assert_eq!(world.registry[far].world_pos, Some(DVec3::new(1000.000001, 0.0, 0.0)));
+        world.shift_origin(Vec3::new(-1000.0, 0.0, 0.0));
+        assert_eq!(world.to_world(world.registry[plain].pos), DVec3::new(1020.0, 5.0, 0.0));
+        validate_world(&world).unwrap();
     }
 }